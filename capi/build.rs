@@ -0,0 +1,35 @@
+// Generate the C header into OUT_DIR on every build. The copy in include/ is only replaced when
+// CHIP8_UPDATE_HEADER is set, and the header test fails while the two differ:
+//
+//     CHIP8_UPDATE_HEADER=1 cargo build -p rust-chip8-capi
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=CHIP8_UPDATE_HEADER");
+    if let Err(err) = generate() {
+        // Leave the library buildable, the header test reports the missing header
+        println!("cargo:warning=unable to generate the C header: {}", err);
+    }
+}
+
+fn generate() -> Result<(), String> {
+    let dir = env::var("CARGO_MANIFEST_DIR").map_err(|err| err.to_string())?;
+    let out_dir = env::var("OUT_DIR").map_err(|err| err.to_string())?;
+    let config = cbindgen::Config::from_file(Path::new(&dir).join("cbindgen.toml"))?;
+    let bindings = cbindgen::Builder::new()
+        .with_crate(&dir)
+        .with_config(config)
+        .generate()
+        .map_err(|err| err.to_string())?;
+    let header = Path::new(&out_dir).join("chip8.h");
+    bindings.write_to_file(&header);
+    if env::var_os("CHIP8_UPDATE_HEADER").is_some() {
+        let target = Path::new(&dir).join("include/chip8.h");
+        fs::copy(&header, &target).map_err(|err| format!("unable to write {}: {}", target.display(), err))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+#ifndef CHIP8_H
+#define CHIP8_H
+
+/* Generated by cbindgen from capi/src/lib.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Version of this API, changed when functions are added
+#define CHIP8_ABI_VERSION 1
+
+// Width of the screen in pixels
+#define CHIP8_WIDTH 64
+
+// Height of the screen in pixels
+#define CHIP8_HEIGHT 32
+
+// Instructions per frame used by chip8_run_frames until chip8_set_tickrate changes it
+#define CHIP8_DEFAULT_TICKRATE 12
+
+// A CHIP-8 machine. Create it with chip8_new and destroy it with chip8_free
+typedef struct Chip8 Chip8;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Version of the API the library implements, to check against CHIP8_ABI_VERSION
+uint32_t chip8_abi_version(void);
+
+// Create a machine with no ROM loaded
+struct Chip8 *chip8_new(void);
+
+// Destroy a machine made by chip8_new. Does nothing if `chip8` is NULL
+//
+// # Safety
+// `chip8` must come from chip8_new and not be used afterwards
+void chip8_free(struct Chip8 *chip8);
+
+// Load a ROM of `length` bytes at 0x200 and start it. Returns 0, or -1 if the ROM doesn't fit in memory,
+// an argument is NULL or the emulator failed
+//
+// # Safety
+// `chip8` must come from chip8_new and `rom` must point to `length` readable bytes
+int32_t chip8_load(struct Chip8 *chip8,
+                   const uint8_t *rom,
+                   size_t length);
+
+// Restart the loaded ROM
+//
+// # Safety
+// `chip8` must come from chip8_new or be NULL
+void chip8_reset(struct Chip8 *chip8);
+
+// Make random numbers reproducible
+//
+// # Safety
+// `chip8` must come from chip8_new or be NULL
+void chip8_seed(struct Chip8 *chip8, uint64_t seed);
+
+// Execute one instruction, without counting the timers down. Returns the program counter afterwards,
+// or 0xFFFF for a NULL machine or if the emulator failed
+//
+// # Safety
+// `chip8` must come from chip8_new or be NULL
+uint16_t chip8_step(struct Chip8 *chip8);
+
+// Run `count` 60Hz frames as fast as possible, counting the timers down once per frame.
+// Returns 1 if the screen changed, 0 if not, or -1 for a NULL machine or if the emulator failed
+//
+// # Safety
+// `chip8` must come from chip8_new or be NULL
+int32_t chip8_run_frames(struct Chip8 *chip8, uint32_t count);
+
+// Set the instructions run by each frame of chip8_run_frames
+//
+// # Safety
+// `chip8` must come from chip8_new or be NULL
+void chip8_set_tickrate(struct Chip8 *chip8, uint32_t tickrate);
+
+// Press (`pressed` non-zero) or release key 0-15. Returns 0, or -1 for a bad key or NULL machine
+//
+// # Safety
+// `chip8` must come from chip8_new or be NULL
+int32_t chip8_set_key(struct Chip8 *chip8, uint8_t key, int32_t pressed);
+
+// The screen, CHIP8_WIDTH * CHIP8_HEIGHT bytes row by row, 1 for a lit pixel and 0 for unlit.
+// The buffer belongs to the machine and changes as it runs. Returns NULL for a NULL machine
+//
+// # Safety
+// `chip8` must come from chip8_new or be NULL. The pointer is valid until the machine is freed
+const uint8_t *chip8_framebuffer(const struct Chip8 *chip8);
+
+// The 4096 bytes of memory, which the caller may read and write between calls.
+// Returns NULL for a NULL machine
+//
+// # Safety
+// `chip8` must come from chip8_new or be NULL. The pointer is valid until the machine is freed
+uint8_t *chip8_memory(struct Chip8 *chip8);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* CHIP8_H */
@@ -0,0 +1,192 @@
+// C API for the emulator core, so C, C++ and other languages can embed the interpreter.
+// The header in include/chip8.h is generated from this file by cbindgen, which copies the `///` comments
+// into it. Functions keep their signatures once released; new features get new functions and a bump of
+// CHIP8_ABI_VERSION. The core doesn't panic on bad programs, but entry points that run it still catch
+// panics, since unwinding into C is undefined behaviour.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use rust_chip8::{chip8, display};
+
+/// Version of this API, changed when functions are added
+pub const CHIP8_ABI_VERSION: u32 = 1;
+
+// cbindgen can only copy literals into the header, so the core's values are repeated here and checked below
+
+/// Width of the screen in pixels
+pub const CHIP8_WIDTH: usize = 64;
+
+/// Height of the screen in pixels
+pub const CHIP8_HEIGHT: usize = 32;
+
+/// Instructions per frame used by chip8_run_frames until chip8_set_tickrate changes it
+pub const CHIP8_DEFAULT_TICKRATE: u32 = 12;
+
+const _: () = assert!(CHIP8_WIDTH == display::WIDTH && CHIP8_HEIGHT == display::HEIGHT);
+const _: () = assert!(CHIP8_DEFAULT_TICKRATE == chip8::DEFAULT_TICKRATE);
+
+/// A CHIP-8 machine. Create it with chip8_new and destroy it with chip8_free
+pub struct Chip8 {
+    chip8: chip8::Chip8,
+    tickrate: u32,
+}
+
+/// Version of the API the library implements, to check against CHIP8_ABI_VERSION
+#[no_mangle]
+pub extern "C" fn chip8_abi_version() -> u32 {
+    CHIP8_ABI_VERSION
+}
+
+/// Create a machine with no ROM loaded
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    Box::into_raw(Box::new(Chip8 { chip8: chip8::Chip8::new(), tickrate: CHIP8_DEFAULT_TICKRATE }))
+}
+
+/// Destroy a machine made by chip8_new. Does nothing if `chip8` is NULL
+///
+/// # Safety
+/// `chip8` must come from chip8_new and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        drop(Box::from_raw(chip8));
+    }
+}
+
+/// Load a ROM of `length` bytes at 0x200 and start it. Returns 0, or -1 if the ROM doesn't fit in memory,
+/// an argument is NULL or the emulator failed
+///
+/// # Safety
+/// `chip8` must come from chip8_new and `rom` must point to `length` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load(chip8: *mut Chip8, rom: *const u8, length: usize) -> i32 {
+    let Some(chip8) = chip8.as_mut() else { return -1 };
+    if rom.is_null() {
+        return -1;
+    }
+    let rom = slice::from_raw_parts(rom, length);
+    guard(-1, || match chip8.chip8.load(rom) {
+        Ok(()) => {
+            chip8.chip8.resume();
+            0
+        }
+        Err(_) => -1,
+    })
+}
+
+/// Restart the loaded ROM
+///
+/// # Safety
+/// `chip8` must come from chip8_new or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(chip8: *mut Chip8) {
+    if let Some(chip8) = chip8.as_mut() {
+        guard((), || chip8.chip8.reset());
+    }
+}
+
+/// Make random numbers reproducible
+///
+/// # Safety
+/// `chip8` must come from chip8_new or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_seed(chip8: *mut Chip8, seed: u64) {
+    if let Some(chip8) = chip8.as_mut() {
+        chip8.chip8.seed(seed);
+    }
+}
+
+/// Execute one instruction, without counting the timers down. Returns the program counter afterwards,
+/// or 0xFFFF for a NULL machine or if the emulator failed
+///
+/// # Safety
+/// `chip8` must come from chip8_new or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(chip8: *mut Chip8) -> u16 {
+    let Some(chip8) = chip8.as_mut() else { return 0xFFFF };
+    guard(0xFFFF, || {
+        chip8.chip8.tick();
+        chip8.chip8.pc
+    })
+}
+
+/// Run `count` 60Hz frames as fast as possible, counting the timers down once per frame.
+/// Returns 1 if the screen changed, 0 if not, or -1 for a NULL machine or if the emulator failed
+///
+/// # Safety
+/// `chip8` must come from chip8_new or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frames(chip8: *mut Chip8, count: u32) -> i32 {
+    let Some(chip8) = chip8.as_mut() else { return -1 };
+    guard(-1, || {
+        let mut drawn = false;
+        for _ in 0..count {
+            chip8.chip8.step_frame(chip8.tickrate);
+            drawn |= chip8.chip8.draw_flag;
+        }
+        i32::from(drawn)
+    })
+}
+
+/// Set the instructions run by each frame of chip8_run_frames
+///
+/// # Safety
+/// `chip8` must come from chip8_new or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_tickrate(chip8: *mut Chip8, tickrate: u32) {
+    if let Some(chip8) = chip8.as_mut() {
+        chip8.tickrate = tickrate;
+    }
+}
+
+/// Press (`pressed` non-zero) or release key 0-15. Returns 0, or -1 for a bad key or NULL machine
+///
+/// # Safety
+/// `chip8` must come from chip8_new or be NULL
+#[no_mangle]
+pub unsafe extern "C" fn chip8_set_key(chip8: *mut Chip8, key: u8, pressed: i32) -> i32 {
+    let Some(chip8) = chip8.as_mut() else { return -1 };
+    match chip8.chip8.key.get_mut(key as usize) {
+        Some(slot) => {
+            *slot = pressed != 0;
+            0
+        }
+        None => -1,
+    }
+}
+
+/// The screen, CHIP8_WIDTH * CHIP8_HEIGHT bytes row by row, 1 for a lit pixel and 0 for unlit.
+/// The buffer belongs to the machine and changes as it runs. Returns NULL for a NULL machine
+///
+/// # Safety
+/// `chip8` must come from chip8_new or be NULL. The pointer is valid until the machine is freed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(chip8: *const Chip8) -> *const u8 {
+    match chip8.as_ref() {
+        // bool is one byte holding 0 or 1
+        Some(chip8) => chip8.chip8.screen.as_ptr().cast(),
+        None => ptr::null(),
+    }
+}
+
+/// The 4096 bytes of memory, which the caller may read and write between calls.
+/// Returns NULL for a NULL machine
+///
+/// # Safety
+/// `chip8` must come from chip8_new or be NULL. The pointer is valid until the machine is freed
+#[no_mangle]
+pub unsafe extern "C" fn chip8_memory(chip8: *mut Chip8) -> *mut u8 {
+    match chip8.as_mut() {
+        Some(chip8) => chip8.chip8.memory.as_mut_ptr(),
+        None => ptr::null_mut(),
+    }
+}
+
+// Run `f`, returning `failed` instead of unwinding into the caller if it panics. The panic message still
+// goes to stderr
+fn guard<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(failed)
+}
@@ -0,0 +1,39 @@
+/* Runs ROMs that used to crash the emulator and checks every call reports success, and that a NULL machine doesn't */
+#include <stdint.h>
+#include <stdio.h>
+
+#include "chip8.h"
+
+static int run(const char *name, const uint8_t *rom, size_t length) {
+    Chip8 *chip8 = chip8_new();
+    if (chip8_load(chip8, rom, length) != 0) {
+        fprintf(stderr, "%s: chip8_load failed\n", name);
+        return 1;
+    }
+    for (int i = 0; i < 1000; i++) {
+        if (chip8_step(chip8) == 0xFFFF) {
+            fprintf(stderr, "%s: chip8_step failed\n", name);
+            return 1;
+        }
+    }
+    if (chip8_run_frames(chip8, 60) < 0) {
+        fprintf(stderr, "%s: chip8_run_frames failed\n", name);
+        return 1;
+    }
+    chip8_free(chip8);
+    return 0;
+}
+
+int main(void) {
+    static const uint8_t empty_return[] = {0x00, 0xEE};
+    static const uint8_t recursion[] = {0x22, 0x00};
+    static const uint8_t end_of_memory[] = {0x6F, 0xFF, 0xAF, 0xFF, 0xFF, 0x55, 0xFF, 0x65, 0xFF, 0x33, 0xD0, 0x1F, 0x1F, 0xFE};
+    int failures = run("empty return", empty_return, sizeof empty_return)
+        + run("recursion", recursion, sizeof recursion)
+        + run("end of memory", end_of_memory, sizeof end_of_memory);
+    if (chip8_step(NULL) != 0xFFFF || chip8_run_frames(NULL, 1) != -1) {
+        fprintf(stderr, "NULL machine: failure not reported\n");
+        failures++;
+    }
+    return failures == 0 ? 0 : 1;
+}
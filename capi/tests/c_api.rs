@@ -0,0 +1,46 @@
+// Checks on the C side of the API: that the header in include/ matches the library, and that a C program
+// linked against the static library survives ROMs that misbehave
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const MANIFEST_DIR: &str = env!("CARGO_MANIFEST_DIR");
+
+#[test]
+fn header_is_up_to_date() {
+    let generated = fs::read_to_string(Path::new(env!("OUT_DIR")).join("chip8.h")).expect("the build didn't generate chip8.h");
+    let checked_in = fs::read_to_string(Path::new(MANIFEST_DIR).join("include/chip8.h")).unwrap();
+    assert!(generated == checked_in, "include/chip8.h is out of date, run `CHIP8_UPDATE_HEADER=1 cargo build -p rust-chip8-capi`");
+}
+
+#[test]
+fn bad_roms_from_c() {
+    // cargo test only builds the Rust library, so build the static one into the same target/<profile>
+    // directory this test runs from
+    let exe = env::current_exe().unwrap();
+    let profile_dir = exe.parent().and_then(Path::parent).unwrap();
+    let mut cargo = Command::new(env::var("CARGO").unwrap_or_else(|_| String::from("cargo")));
+    cargo.args(["build", "--lib", "-p", "rust-chip8-capi", "--target-dir"]).arg(profile_dir.parent().unwrap());
+    if profile_dir.ends_with("release") {
+        cargo.arg("--release");
+    }
+    assert!(cargo.status().expect("unable to run cargo").success(), "unable to build the static library");
+    let library = profile_dir.join("libchip8_capi.a");
+    let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("bad_rom");
+
+    let compiler = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let status = Command::new(compiler)
+        .arg("-I")
+        .arg(Path::new(MANIFEST_DIR).join("include"))
+        .arg(Path::new(MANIFEST_DIR).join("tests/bad_rom.c"))
+        .arg(&library)
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&program)
+        .status()
+        .expect("unable to run the C compiler");
+    assert!(status.success(), "unable to build tests/bad_rom.c against {}", library.display());
+
+    let status = Command::new(&program).status().unwrap();
+    assert!(status.success(), "tests/bad_rom.c failed");
+}
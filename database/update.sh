@@ -0,0 +1,17 @@
+#!/bin/sh
+# Replace the embedded database with the latest files from the CHIP-8 database, along with its licence.
+# Run from anywhere, then rebuild:
+#
+#     database/update.sh && cargo build --release
+set -eu
+
+UPSTREAM=https://raw.githubusercontent.com/chip-8/chip-8-database/HEAD
+cd "$(dirname "$0")"
+for file in database/programs.json database/sha1-hashes.json database/platforms.json LICENSE; do
+    curl -fsSL "$UPSTREAM/$file" -o "$(basename "$file").new"
+done
+for file in programs.json sha1-hashes.json platforms.json; do
+    mv "$file.new" "$file"
+done
+mv LICENSE.new LICENSE
+echo "Updated the ROM database from $UPSTREAM"
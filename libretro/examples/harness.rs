@@ -0,0 +1,204 @@
+// Minimal libretro frontend for checking the core without RetroArch. It loads the core library, runs a ROM
+// for a number of frames with no keys pressed, prints the screen, then checks that restoring a save state
+// replays the same frames:
+//
+//     cargo build -p rust-chip8-libretro
+//     cargo run -p rust-chip8-libretro --example harness -- target/debug/libchip8_libretro.so "roms/IBM Logo.ch8" 60
+
+use std::env;
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::fs;
+use std::process;
+use std::ptr;
+use std::sync::Mutex;
+
+use libloading::{Library, Symbol};
+
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+#[repr(C)]
+struct RetroSystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+struct RetroSystemAvInfo {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+struct RetroGameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct RetroVariable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+// What the core has sent back: the last frame as (width, height, pixels) and the audio samples so far
+static FRAME: Mutex<(usize, usize, Vec<u32>)> = Mutex::new((0, 0, Vec::new()));
+static SAMPLES: Mutex<usize> = Mutex::new(0);
+
+unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => *(data as *const c_uint) == RETRO_PIXEL_FORMAT_XRGB8888,
+        RETRO_ENVIRONMENT_SET_VARIABLES => {
+            let mut variable = data as *const RetroVariable;
+            while !(*variable).key.is_null() {
+                let key = CStr::from_ptr((*variable).key).to_string_lossy();
+                let value = CStr::from_ptr((*variable).value).to_string_lossy();
+                println!("option {} = {}", key, value);
+                variable = variable.add(1);
+            }
+            true
+        }
+        // Everything else, including option values, is left to the core's defaults
+        _ => false,
+    }
+}
+
+unsafe extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    if data.is_null() {
+        return;
+    }
+    let (width, height) = (width as usize, height as usize);
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in 0..height {
+        let line = (data as *const u8).add(row * pitch) as *const u32;
+        pixels.extend_from_slice(std::slice::from_raw_parts(line, width));
+    }
+    *FRAME.lock().unwrap() = (width, height, pixels);
+}
+
+unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {
+    *SAMPLES.lock().unwrap() += 1;
+}
+
+unsafe extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    *SAMPLES.lock().unwrap() += frames;
+    frames
+}
+
+unsafe extern "C" fn input_poll() {}
+
+unsafe extern "C" fn input_state(_port: c_uint, _device: c_uint, _index: c_uint, _id: c_uint) -> i16 {
+    0
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        fail(format!("usage: {} <core library> <rom> [frames]", args[0]));
+    }
+    let frames: u32 = args.get(3).map_or(Ok(60), |frames| frames.parse()).unwrap_or_else(|err| fail(format!("bad frame count: {}", err)));
+    let rom = fs::read(&args[2]).unwrap_or_else(|err| fail(format!("unable to read {}: {}", args[2], err)));
+
+    unsafe {
+        let core = Library::new(&args[1]).unwrap_or_else(|err| fail(format!("unable to load {}: {}", args[1], err)));
+        macro_rules! symbol {
+            ($name:ident: $type:ty) => {
+                let $name: Symbol<$type> = core
+                    .get(concat!(stringify!($name), "\0").as_bytes())
+                    .unwrap_or_else(|err| fail(format!("{}: {}", stringify!($name), err)));
+            };
+        }
+        symbol!(retro_api_version: unsafe extern "C" fn() -> c_uint);
+        symbol!(retro_get_system_info: unsafe extern "C" fn(*mut RetroSystemInfo));
+        symbol!(retro_get_system_av_info: unsafe extern "C" fn(*mut RetroSystemAvInfo));
+        symbol!(retro_set_environment: unsafe extern "C" fn(unsafe extern "C" fn(c_uint, *mut c_void) -> bool));
+        symbol!(retro_set_video_refresh: unsafe extern "C" fn(unsafe extern "C" fn(*const c_void, c_uint, c_uint, usize)));
+        symbol!(retro_set_audio_sample: unsafe extern "C" fn(unsafe extern "C" fn(i16, i16)));
+        symbol!(retro_set_audio_sample_batch: unsafe extern "C" fn(unsafe extern "C" fn(*const i16, usize) -> usize));
+        symbol!(retro_set_input_poll: unsafe extern "C" fn(unsafe extern "C" fn()));
+        symbol!(retro_set_input_state: unsafe extern "C" fn(unsafe extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16));
+        symbol!(retro_init: unsafe extern "C" fn());
+        symbol!(retro_deinit: unsafe extern "C" fn());
+        symbol!(retro_load_game: unsafe extern "C" fn(*const RetroGameInfo) -> bool);
+        symbol!(retro_unload_game: unsafe extern "C" fn());
+        symbol!(retro_run: unsafe extern "C" fn());
+        symbol!(retro_serialize_size: unsafe extern "C" fn() -> usize);
+        symbol!(retro_serialize: unsafe extern "C" fn(*mut c_void, usize) -> bool);
+        symbol!(retro_unserialize: unsafe extern "C" fn(*const c_void, usize) -> bool);
+
+        let mut info: RetroSystemInfo = std::mem::zeroed();
+        retro_get_system_info(&mut info);
+        println!(
+            "{} {}, API version {}, extensions {}",
+            CStr::from_ptr(info.library_name).to_string_lossy(),
+            CStr::from_ptr(info.library_version).to_string_lossy(),
+            retro_api_version(),
+            CStr::from_ptr(info.valid_extensions).to_string_lossy(),
+        );
+
+        retro_set_environment(environment);
+        retro_set_video_refresh(video_refresh);
+        retro_set_audio_sample(audio_sample);
+        retro_set_audio_sample_batch(audio_sample_batch);
+        retro_set_input_poll(input_poll);
+        retro_set_input_state(input_state);
+        retro_init();
+
+        let game = RetroGameInfo { path: ptr::null(), data: rom.as_ptr().cast(), size: rom.len(), meta: ptr::null() };
+        if !retro_load_game(&game) {
+            fail(format!("the core refused {}", args[2]));
+        }
+        let mut av: RetroSystemAvInfo = std::mem::zeroed();
+        retro_get_system_av_info(&mut av);
+        println!("{}x{} at {} fps, {} Hz audio", av.base_width, av.base_height, av.fps, av.sample_rate);
+
+        for _ in 0..frames {
+            retro_run();
+        }
+        let (width, height, pixels) = FRAME.lock().unwrap().clone();
+        let background = pixels.first().copied().unwrap_or(0);
+        for row in pixels.chunks(width.max(1)) {
+            println!("{}", row.iter().map(|&pixel| if pixel == background { '.' } else { '#' }).collect::<String>());
+        }
+        println!("{} frames of {}x{} video, {} audio samples", frames, width, height, *SAMPLES.lock().unwrap());
+
+        let mut state = vec![0u8; retro_serialize_size()];
+        if !retro_serialize(state.as_mut_ptr().cast(), state.len()) {
+            fail(String::from("the core couldn't save its state"));
+        }
+        let run = || {
+            for _ in 0..frames {
+                retro_run();
+            }
+            FRAME.lock().unwrap().2.clone()
+        };
+        let first = run();
+        if !retro_unserialize(state.as_ptr().cast(), state.len()) {
+            fail(String::from("the core couldn't restore its state"));
+        }
+        let second = run();
+        if first != second {
+            fail(String::from("running on from a restored state gave a different screen"));
+        }
+        println!("{} byte save state restores and replays the same frames", state.len());
+
+        retro_unload_game();
+        retro_deinit();
+    }
+}
@@ -0,0 +1,398 @@
+// libretro core, so RetroArch and other libretro frontends can run CHIP-8 ROMs on this emulator.
+// The frontend loads the library, calls the retro_* functions below and handles the window, sound, input
+// mapping, save states and rewind itself. See README.md for building and installing it.
+//
+// Frames run at 60Hz with a fixed number of instructions each, set by the "Instructions per frame" option.
+// The beeper is rendered at 44.1kHz, and save states are the core's Snapshot encoded with bincode, which
+// always has the same size as libretro expects.
+
+// The retro_* functions are only called by the frontend, which follows the rules in libretro.h
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+use rust_chip8::chip8::{Chip8, Quirks, Snapshot, DEFAULT_TICKRATE};
+use rust_chip8::display::{Palette, HEIGHT, WIDTH};
+use rust_chip8::timing::Timing;
+use rust_chip8::tone::{AudioConfig, Tone};
+
+mod ffi;
+
+use ffi::*;
+
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / 60) as usize;
+
+// RetroPad buttons for the 16 keys. The d-pad is 2/4/6/8 and A is 5, which is what most games use for
+// moving and firing; the other buttons get the remaining keys so every key can be pressed
+const KEYMAP: [(c_uint, u8, &CStr); 16] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2, c"Key 2 (up)"),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8, c"Key 8 (down)"),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4, c"Key 4 (left)"),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6, c"Key 6 (right)"),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5, c"Key 5"),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0, c"Key 0"),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0x7, c"Key 7"),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x9, c"Key 9"),
+    (RETRO_DEVICE_ID_JOYPAD_L, 0x1, c"Key 1"),
+    (RETRO_DEVICE_ID_JOYPAD_R, 0x3, c"Key 3"),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xA, c"Key A"),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xB, c"Key B"),
+    (RETRO_DEVICE_ID_JOYPAD_L2, 0xC, c"Key C"),
+    (RETRO_DEVICE_ID_JOYPAD_R2, 0xD, c"Key D"),
+    (RETRO_DEVICE_ID_JOYPAD_L3, 0xE, c"Key E"),
+    (RETRO_DEVICE_ID_JOYPAD_R3, 0xF, c"Key F"),
+];
+
+// Core options shown by the frontend, as "description; default|other values"
+const TICKRATE_OPTION: &CStr = c"chip8_tickrate";
+const QUIRKS_OPTION: &CStr = c"chip8_quirks";
+const TIMING_OPTION: &CStr = c"chip8_timing";
+const PALETTE_OPTION: &CStr = c"chip8_palette";
+const OPTIONS: [(&CStr, &CStr); 4] = [
+    (TICKRATE_OPTION, c"Instructions per frame; 12|8|10|15|20|30|50|100|200|500|1000"),
+    (QUIRKS_OPTION, c"Quirks; superchip|original|modern|chip48|xochip"),
+    (TIMING_OPTION, c"Timing; fixed|vip"),
+    (PALETTE_OPTION, c"Colours; green|white|amber|lcd"),
+];
+
+// Frontend callbacks, set before retro_init
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+// The loaded game
+struct Core {
+    chip8: Chip8,
+    tone: Tone,
+    tickrate: u32,
+    palette: Palette,
+    video: [u32; WIDTH * HEIGHT], // XRGB8888
+    audio: Vec<i16>,              // Interleaved stereo
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+// Run `f`, returning `failed` instead of unwinding into the frontend if it panics. The game is unloaded then,
+// so later calls find no core rather than a broken one
+fn catch<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        CORE.clear_poison();
+        *CORE.lock().unwrap() = None;
+        failed
+    })
+}
+
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+// Ask the frontend for something, returning false if it can't or there is no frontend yet
+unsafe fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match callbacks().environment {
+        Some(environment) => environment(cmd, data),
+        None => false,
+    }
+}
+
+// Current value of a core option, if the frontend has one
+unsafe fn option(key: &CStr) -> Option<String> {
+    let mut variable = RetroVariable { key: key.as_ptr(), value: ptr::null() };
+    if !environment(RETRO_ENVIRONMENT_GET_VARIABLE, &mut variable as *mut RetroVariable as *mut c_void) || variable.value.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(variable.value).to_string_lossy().into_owned())
+}
+
+impl Core {
+    // Pick up the core options. Values the core doesn't understand are ignored
+    unsafe fn apply_options(&mut self) {
+        if let Some(tickrate) = option(TICKRATE_OPTION).and_then(|value| value.parse().ok()) {
+            self.tickrate = tickrate;
+        }
+        if let Some(quirks) = option(QUIRKS_OPTION).and_then(|value| value.parse::<Quirks>().ok()) {
+            self.chip8.quirks = quirks;
+        }
+        if let Some(timing) = option(TIMING_OPTION).and_then(|value| value.parse::<Timing>().ok()) {
+            self.chip8.timing = timing;
+        }
+        if let Some(palette) = option(PALETTE_OPTION).and_then(|value| value.parse::<Palette>().ok()) {
+            self.palette = palette;
+        }
+    }
+
+    fn render_video(&mut self) {
+        let xrgb = |[r, g, b, _]: [u8; 4]| u32::from_be_bytes([0, r, g, b]);
+        let (on, off) = (xrgb(self.palette.on), xrgb(self.palette.off));
+        for (pixel, &lit) in self.video.iter_mut().zip(self.chip8.screen.iter()) {
+            *pixel = if lit { on } else { off };
+        }
+    }
+
+    // One frame of the beeper, gated by the sound timer like the emulator's own audio
+    fn render_audio(&mut self) {
+        let gate = self.chip8.timer.get_st() != 0;
+        self.audio.clear();
+        for _ in 0..SAMPLES_PER_FRAME {
+            let sample = (self.tone.next_sample(gate) * i16::MAX as f32) as i16;
+            self.audio.extend([sample, sample]);
+        }
+    }
+
+    fn serialize(&mut self) -> Result<Vec<u8>, String> {
+        let snapshot = self.chip8.snapshot()?;
+        bincode::serialize(&snapshot).map_err(|err| err.to_string())
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(callback: RetroEnvironment) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+    let mut variables: Vec<RetroVariable> = OPTIONS
+        .iter()
+        .map(|(key, value)| RetroVariable { key: key.as_ptr(), value: value.as_ptr() })
+        .collect();
+    variables.push(RetroVariable { key: ptr::null(), value: ptr::null() });
+    callback(RETRO_ENVIRONMENT_SET_VARIABLES, variables.as_mut_ptr() as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: RetroVideoRefresh) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+// Audio goes out a frame at a time through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: RetroAudioSample) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: RetroAudioSampleBatch) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: RetroInputPoll) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: RetroInputState) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    let Some(info) = info.as_mut() else { return };
+    info.library_name = c"rust-chip8".as_ptr();
+    info.library_version = concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast();
+    info.valid_extensions = c"ch8|sc8|xo8".as_ptr();
+    info.need_fullpath = false;
+    info.block_extract = false;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    let Some(info) = info.as_mut() else { return };
+    info.geometry = RetroGameGeometry {
+        base_width: WIDTH as c_uint,
+        base_height: HEIGHT as c_uint,
+        max_width: WIDTH as c_uint,
+        max_height: HEIGHT as c_uint,
+        aspect_ratio: WIDTH as f32 / HEIGHT as f32,
+    };
+    info.timing = RetroSystemTiming { fps: FPS, sample_rate: SAMPLE_RATE as f64 };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let Some(game) = game.as_ref() else { return false };
+    if game.data.is_null() {
+        return false;
+    }
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+        eprintln!("rust-chip8: the frontend doesn't support XRGB8888 video");
+        return false;
+    }
+    let mut descriptors: Vec<RetroInputDescriptor> = KEYMAP
+        .iter()
+        .map(|&(id, _, description)| RetroInputDescriptor {
+            port: 0,
+            device: RETRO_DEVICE_JOYPAD,
+            index: 0,
+            id,
+            description: description.as_ptr(),
+        })
+        .collect();
+    descriptors.push(RetroInputDescriptor { port: 0, device: 0, index: 0, id: 0, description: ptr::null() });
+    environment(RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS, descriptors.as_mut_ptr() as *mut c_void);
+
+    let mut core = Core {
+        chip8: Chip8::new(),
+        tone: Tone::new(AudioConfig::default(), SAMPLE_RATE as f32),
+        tickrate: DEFAULT_TICKRATE,
+        palette: Palette::default(),
+        video: [0; WIDTH * HEIGHT],
+        audio: Vec::with_capacity(SAMPLES_PER_FRAME * 2),
+    };
+    core.apply_options();
+    if let Err(err) = core.chip8.load(slice::from_raw_parts(game.data.cast(), game.size)) {
+        eprintln!("rust-chip8: {}", err);
+        return false;
+    }
+    core.chip8.resume();
+    *CORE.lock().unwrap() = Some(core);
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const RetroGameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.chip8.reset();
+        core.chip8.resume();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    catch((), || run_frame());
+}
+
+unsafe fn run_frame() {
+    let callbacks = callbacks();
+    let mut updated = false;
+    environment(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut c_void);
+
+    let mut guard = CORE.lock().unwrap();
+    let Some(core) = guard.as_mut() else { return };
+    if updated {
+        core.apply_options();
+    }
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+    if let Some(input_state) = callbacks.input_state {
+        for &(id, key, _) in KEYMAP.iter() {
+            core.chip8.key[key as usize] = input_state(0, RETRO_DEVICE_JOYPAD, 0, id) != 0;
+        }
+    }
+
+    core.chip8.step_frame(core.tickrate);
+    core.render_video();
+    core.render_audio();
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        video_refresh(core.video.as_ptr().cast(), WIDTH as c_uint, HEIGHT as c_uint, WIDTH * 4);
+    }
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        audio_sample_batch(core.audio.as_ptr(), SAMPLES_PER_FRAME);
+    }
+}
+
+// Snapshots have fixed-size fields, so every state is the same size
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) => core.serialize().map_or(0, |state| state.len()),
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let mut guard = CORE.lock().unwrap();
+    let Some(core) = guard.as_mut() else { return false };
+    match core.serialize() {
+        Ok(state) if state.len() <= size && !data.is_null() => {
+            ptr::copy_nonoverlapping(state.as_ptr(), data.cast(), state.len());
+            true
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let state = slice::from_raw_parts(data.cast(), size);
+    catch(false, || {
+        let Ok(snapshot) = bincode::deserialize::<Snapshot>(state) else { return false };
+        // restore checks the registers, so a corrupt state can't crash the next frame
+        let mut guard = CORE.lock().unwrap();
+        guard.as_mut().is_some_and(|core| core.chip8.restore(&snapshot).is_ok())
+    })
+}
+
+// The frontend's cheats aren't supported
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+// The 4K of memory, for the frontend's memory viewer and achievements
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.chip8.memory.as_mut_ptr().cast(),
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => core.chip8.memory.len(),
+        _ => 0,
+    }
+}
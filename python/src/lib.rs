@@ -0,0 +1,298 @@
+// Python bindings for the emulator core, so experiments can be scripted against the same interpreter the
+// emulator runs. Build a wheel with `maturin build --release` in this directory, then:
+//
+//     import chip8
+//     machine = chip8.Chip8(seed=1)
+//     machine.load_file("roms/IBM Logo.ch8")
+//     machine.run_frames(60)
+//     pixels = numpy.frombuffer(machine.screen(), dtype=numpy.uint8).reshape(chip8.HEIGHT, chip8.WIDTH)
+//
+// or, for reinforcement learning with a spec like the TOML one `serve --env` takes, as JSON:
+//
+//     env = chip8.Env(rom, spec=json.dumps({"score": {"address": 0x2F0, "format": "bcd"}}))
+//     observation = env.reset(seed=1)
+//     observation, reward, done = env.step(action)
+
+use std::fs;
+
+use pyo3::exceptions::{PyIndexError, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use rust_chip8::chip8::{self as core, Quirks, Snapshot, DEFAULT_TICKRATE};
+use rust_chip8::display::{self, HEIGHT, WIDTH};
+use rust_chip8::env::{self as core_env, EnvSpec};
+use rust_chip8::timing::Timing;
+
+// The machine isn't shareable between threads, so it stays on the thread that created it
+#[pyclass(unsendable)]
+struct Chip8 {
+    chip8: core::Chip8,
+    tickrate: u32,
+}
+
+#[pymethods]
+impl Chip8 {
+    #[new]
+    #[pyo3(signature = (seed=None, tickrate=DEFAULT_TICKRATE, quirks=None))]
+    fn new(seed: Option<u64>, tickrate: u32, quirks: Option<&str>) -> PyResult<Self> {
+        Ok(Chip8 { chip8: new_machine(seed, quirks)?, tickrate })
+    }
+
+    // Load a ROM from bytes and start it
+    fn load(&mut self, rom: &[u8]) -> PyResult<()> {
+        self.chip8.load(rom).map_err(PyValueError::new_err)?;
+        self.chip8.resume();
+        Ok(())
+    }
+
+    fn load_file(&mut self, path: &str) -> PyResult<()> {
+        let rom = fs::read(path).map_err(|err| PyOSError::new_err(format!("unable to read {}: {}", path, err)))?;
+        self.load(&rom)
+    }
+
+    // Restart the loaded ROM
+    fn reset(&mut self) {
+        self.chip8.reset();
+    }
+
+    // Make CXNN deterministic from now on
+    fn seed(&mut self, seed: u64) {
+        self.chip8.seed(seed);
+    }
+
+    // Run single instructions, without counting the timers down
+    #[pyo3(signature = (count=1))]
+    fn step(&mut self, count: u64) -> PyResult<()> {
+        if self.chip8.has_vip() {
+            return Err(PyValueError::new_err("single instructions can't be stepped on the VIP"));
+        }
+        for _ in 0..count {
+            self.chip8.tick();
+        }
+        Ok(())
+    }
+
+    // Run 60Hz frames as fast as possible. Returns whether the screen changed
+    #[pyo3(signature = (count=1))]
+    fn run_frames(&mut self, count: u64) -> bool {
+        let mut drawn = false;
+        for _ in 0..count {
+            self.chip8.step_frame(self.tickrate);
+            drawn |= self.chip8.draw_flag;
+        }
+        drawn
+    }
+
+    fn set_key(&mut self, key: usize, pressed: bool) -> PyResult<()> {
+        let slot = self.chip8.key.get_mut(key).ok_or_else(|| PyIndexError::new_err("key must be 0-15"))?;
+        *slot = pressed;
+        Ok(())
+    }
+
+    // One byte per pixel, 1 for lit, row by row. Reshape to (HEIGHT, WIDTH) with numpy
+    fn screen<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &display::to_indexed(&self.chip8.screen, 1))
+    }
+
+    // 8 pixels per byte, leftmost pixel in the top bit
+    fn screen_bits<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &display::to_bits(&self.chip8.screen))
+    }
+
+    fn read_memory<'py>(&self, py: Python<'py>, address: usize, length: usize) -> PyResult<Bound<'py, PyBytes>> {
+        let data = self
+            .chip8
+            .memory
+            .get(address..address.saturating_add(length))
+            .ok_or_else(|| PyIndexError::new_err("range is outside memory"))?;
+        Ok(PyBytes::new(py, data))
+    }
+
+    // Poked bytes count as writes, like the emulator's memory editor
+    fn write_memory(&mut self, address: usize, data: &[u8]) -> PyResult<()> {
+        if address.saturating_add(data.len()) > self.chip8.memory.len() {
+            return Err(PyIndexError::new_err("range is outside memory"));
+        }
+        for (offset, &value) in data.iter().enumerate() {
+            self.chip8.poke((address + offset) as u16, value);
+        }
+        Ok(())
+    }
+
+    // The machine state as JSON, for `load_state`
+    fn save_state<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let snapshot = self.chip8.snapshot().map_err(PyValueError::new_err)?;
+        let json = serde_json::to_vec(&snapshot).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(PyBytes::new(py, &json))
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        let snapshot: Snapshot = serde_json::from_slice(state).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        self.chip8.restore(&snapshot).map_err(PyValueError::new_err)
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.chip8.pc
+    }
+
+    #[getter]
+    fn i(&self) -> u16 {
+        self.chip8.i_register
+    }
+
+    #[getter]
+    fn sp(&self) -> u16 {
+        self.chip8.stack_ptr
+    }
+
+    #[getter]
+    fn v(&self) -> Vec<u8> {
+        self.chip8.v_register.to_vec()
+    }
+
+    #[getter]
+    fn delay_timer(&mut self) -> u8 {
+        self.chip8.timer.get_dt()
+    }
+
+    #[getter]
+    fn sound_timer(&mut self) -> u8 {
+        self.chip8.timer.get_st()
+    }
+
+    #[getter]
+    fn frames(&self) -> u64 {
+        self.chip8.frames
+    }
+
+    #[getter]
+    fn tickrate(&self) -> u32 {
+        self.tickrate
+    }
+
+    #[setter]
+    fn set_tickrate(&mut self, tickrate: u32) {
+        self.tickrate = tickrate;
+    }
+
+    // Quirk profile by name: original, modern, chip48, superchip or xochip
+    #[setter]
+    fn set_quirks(&mut self, name: &str) -> PyResult<()> {
+        self.chip8.quirks = name.parse::<Quirks>().map_err(PyValueError::new_err)?;
+        Ok(())
+    }
+
+    // fixed or vip
+    #[setter]
+    fn set_timing(&mut self, name: &str) -> PyResult<()> {
+        self.chip8.timing = name.parse::<Timing>().map_err(PyValueError::new_err)?;
+        Ok(())
+    }
+}
+
+// The Gym-style environment from the core, which owns its own machine. Without a spec every key is an action on
+// its own and there is no reward
+#[pyclass(unsendable)]
+struct Env {
+    env: core_env::Env,
+}
+
+#[pymethods]
+impl Env {
+    #[new]
+    #[pyo3(signature = (rom, spec=None, tickrate=DEFAULT_TICKRATE, quirks=None))]
+    fn new(rom: &[u8], spec: Option<&str>, tickrate: u32, quirks: Option<&str>) -> PyResult<Self> {
+        let spec = match spec {
+            Some(json) => serde_json::from_str::<EnvSpec>(json).map_err(|err| PyValueError::new_err(err.to_string()))?,
+            None => EnvSpec::default(),
+        };
+        spec.check().map_err(PyValueError::new_err)?;
+        let mut chip8 = new_machine(None, quirks)?;
+        chip8.load(rom).map_err(PyValueError::new_err)?;
+        Ok(Env { env: core_env::Env::new(chip8, spec, tickrate) })
+    }
+
+    // Restart the game and return the first observation, 8 pixels per byte like `Chip8.screen_bits`
+    #[pyo3(signature = (seed=None))]
+    fn reset<'py>(&mut self, py: Python<'py>, seed: Option<u64>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.env.reset(seed))
+    }
+
+    // Returns (observation, reward, done)
+    fn step<'py>(&mut self, py: Python<'py>, action: usize) -> PyResult<(Bound<'py, PyBytes>, f64, bool)> {
+        let step = self.env.step(action).map_err(PyValueError::new_err)?;
+        Ok((PyBytes::new(py, &step.observation), step.reward, step.done))
+    }
+
+    // The screen as a line of text per row
+    fn render(&self) -> Vec<String> {
+        self.env.render()
+    }
+
+    #[getter]
+    fn action_count(&self) -> usize {
+        self.env.spec().action_count()
+    }
+
+    #[getter]
+    fn frame_skip(&self) -> u32 {
+        self.env.spec().frame_skip
+    }
+}
+
+fn new_machine(seed: Option<u64>, quirks: Option<&str>) -> PyResult<core::Chip8> {
+    let mut chip8 = core::Chip8::new();
+    if let Some(seed) = seed {
+        chip8.seed(seed);
+    }
+    if let Some(quirks) = quirks {
+        chip8.quirks = quirks.parse::<Quirks>().map_err(PyValueError::new_err)?;
+    }
+    Ok(chip8)
+}
+
+#[pymodule]
+fn chip8(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Chip8>()?;
+    module.add_class::<Env>()?;
+    module.add("WIDTH", WIDTH)?;
+    module.add("HEIGHT", HEIGHT)?;
+    module.add("DEFAULT_TICKRATE", DEFAULT_TICKRATE)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs without maturin, against the libpython the test links to
+    #[test]
+    fn run_and_restore_a_rom() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mut machine = Chip8::new(Some(1), DEFAULT_TICKRATE, None).unwrap();
+            machine.load(include_bytes!("../../roms/IBM Logo.ch8")).unwrap();
+            assert!(machine.run_frames(60));
+            let screen = machine.screen(py).as_bytes().to_vec();
+            assert_eq!(screen.len(), WIDTH * HEIGHT);
+            assert!(screen.contains(&1));
+
+            let state = machine.save_state(py).unwrap();
+            let pc = machine.pc();
+            machine.reset();
+            assert!(!machine.screen(py).as_bytes().contains(&1));
+            machine.load_state(state.as_bytes()).unwrap();
+            assert_eq!((machine.pc(), machine.screen(py).as_bytes()), (pc, &screen[..]));
+            assert!(machine.load_state(b"{}").is_err());
+
+            // Writes go through poke, so they show up like the program's own
+            machine.write_memory(0x300, &[1, 2]).unwrap();
+            assert!(machine.write_memory(0xFFF, &[1, 2]).is_err());
+            let written = machine.chip8.take_writes();
+            assert!(written[0x300] && written[0x301] && !written[0xFFF]);
+            assert_eq!(machine.read_memory(py, 0x300, 2).unwrap().as_bytes(), [1, 2]);
+        });
+    }
+}
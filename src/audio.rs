@@ -1,51 +1,74 @@
-use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use cpal::Stream;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 
+use crate::tone::{AudioConfig, Tone};
+
 pub struct Audio {
-    stream: Stream,
+    // The stream keeps running, the gate tells the envelope whether to fade in or out.
+    // No stream means the null backend: the gate is still tracked but nothing is played
+    _stream: Option<Stream>,
+    gate: Arc<AtomicBool>,
 }
 
 impl Audio {
-    pub fn new() -> Self {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("failed to find a default output device");
-        let config = device.default_output_config().unwrap();
+    // Open the default output device, falling back to the null backend if there is none
+    pub fn new(audio_config: AudioConfig) -> Self {
+        let gate = Arc::new(AtomicBool::new(false));
+        match Self::open(audio_config, Arc::clone(&gate)) {
+            Ok(stream) => Audio { _stream: Some(stream), gate },
+            Err(err) => {
+                eprintln!("warning: {}, audio disabled", err);
+                Audio { _stream: None, gate }
+            }
+        }
+    }
 
-        let stream = match config.sample_format() {
-            cpal::SampleFormat::F32 => Self::run::<f32>(&device, &config.into()),
-            cpal::SampleFormat::I16 => Self::run::<i16>(&device, &config.into()),
-            cpal::SampleFormat::U16 => Self::run::<u16>(&device, &config.into()),
-        };
-        Audio { stream }
+    // Audio backend that never touches an output device
+    pub fn null() -> Self {
+        Audio {
+            _stream: None,
+            gate: Arc::new(AtomicBool::new(false)),
+        }
     }
 
     pub fn play(&self) {
-        self.stream.play().unwrap();
+        self.gate.store(true, Ordering::Relaxed);
     }
 
     pub fn pause(&self) {
-        self.stream.pause().unwrap();
+        self.gate.store(false, Ordering::Relaxed);
     }
 
-    fn run<T>(device: &cpal::Device, config: &cpal::StreamConfig) -> Stream
+    fn open(audio_config: AudioConfig, gate: Arc<AtomicBool>) -> Result<Stream, String> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or("failed to find a default output device")?;
+        let config = device
+            .default_output_config()
+            .map_err(|err| format!("failed to get output config: {}", err))?;
+
+        match config.sample_format() {
+            cpal::SampleFormat::F32 => Self::run::<f32>(&device, &config.into(), audio_config, gate),
+            cpal::SampleFormat::I16 => Self::run::<i16>(&device, &config.into(), audio_config, gate),
+            cpal::SampleFormat::U16 => Self::run::<u16>(&device, &config.into(), audio_config, gate),
+        }
+    }
+
+    fn run<T>(device: &cpal::Device, config: &cpal::StreamConfig, audio_config: AudioConfig, gate: Arc<AtomicBool>) -> Result<Stream, String>
         where
             T: cpal::Sample,
     {
         let sample_rate = config.sample_rate.0 as f32;
         let channels = config.channels as usize;
 
-        // Produce a sinusoid of maximum amplitude.
-        let mut sample_clock = 0f32;
-        let mut next_value = move || {
-            sample_clock = (sample_clock + 1.0) % sample_rate;
-            (sample_clock * 440.0 * 2.0 * PI / sample_rate).sin()
-        };
+        let mut tone = Tone::new(audio_config, sample_rate);
+        let mut next_value = move || tone.next_sample(gate.load(Ordering::Relaxed));
 
-        let err_fn = |err| println!("an error occurred on stream: {}", err);
+        let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
 
         let stream = device
             .build_output_stream(
@@ -53,9 +76,9 @@ impl Audio {
                 move |data: &mut [T], _| Self::write_data(data, channels, &mut next_value),
                 err_fn,
             )
-            .unwrap();
-        stream.pause().unwrap();
-        stream
+            .map_err(|err| format!("failed to build output stream: {}", err))?;
+        stream.play().map_err(|err| format!("failed to start output stream: {}", err))?;
+        Ok(stream)
     }
 
     fn write_data<T>(output: &mut [T], channels: usize, next_sample: &mut dyn FnMut() -> f32)
@@ -69,4 +92,4 @@ impl Audio {
             }
         }
     }
-}
\ No newline at end of file
+}
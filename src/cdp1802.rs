@@ -0,0 +1,534 @@
+// RCA CDP1802 CPU core, as used in the COSMAC VIP.
+// Reference: RCA CDP1802 datasheet and the "User Manual for the CDP1802 COSMAC Microprocessor" (MPM-201).
+//
+// Timing is counted in machine cycles of 8 clock pulses. Every instruction takes 2 machine cycles
+// (fetch and execute) except the long branches and skips, which take 3. DMA and interrupt requests are
+// sampled between instructions, and each DMA transfer or interrupt entry takes 1 machine cycle.
+
+// What the CPU is connected to
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    // OUT 1-7, with the byte the CPU put on the bus
+    fn output(&mut self, port: u8, value: u8);
+    // INP 1-7, returning the byte a device put on the bus
+    fn input(&mut self, port: u8) -> u8;
+    // State of the EF1-EF4 input flags, true when asserted
+    fn flag(&mut self, flag: u8) -> bool;
+}
+
+pub struct Cdp1802 {
+    pub r: [u16; 16], // Scratchpad registers
+    pub p: u8,        // Which register is the program counter
+    pub x: u8,        // Which register is the data pointer
+    pub d: u8,        // Accumulator
+    pub df: bool,     // Carry/borrow flag
+    pub t: u8,        // X and P saved on interrupt
+    pub ie: bool,     // Interrupts enabled
+    pub q: bool,      // Output flip-flop, drives the VIP's speaker
+    pub idle: bool,   // Waiting for DMA or an interrupt after IDL
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Cdp1802::new()
+    }
+}
+
+impl Cdp1802 {
+    pub fn new() -> Self {
+        let mut cpu = Cdp1802 {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        };
+        cpu.reset();
+        cpu
+    }
+
+    // What the RESET line does: X, P and R0 are cleared, Q is reset and interrupts are enabled.
+    // The other registers keep whatever they held
+    pub fn reset(&mut self) {
+        self.x = 0;
+        self.p = 0;
+        self.r[0] = 0;
+        self.q = false;
+        self.ie = true;
+        self.idle = false;
+    }
+
+    // One DMA-out cycle: the byte at R0 goes to the bus and R0 moves on. Returns the byte
+    pub fn dma_out(&mut self, bus: &mut impl Bus) -> u8 {
+        let value = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        value
+    }
+
+    // Take an interrupt if they are enabled: save X and P in T, then continue with P = 1 and X = 2.
+    // Returns the machine cycles used
+    pub fn interrupt(&mut self) -> u32 {
+        if !self.ie {
+            return 0;
+        }
+        self.t = self.x << 4 | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+        1
+    }
+
+    // Execute one instruction, returning the machine cycles it took
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.fetch(bus);
+        let n = (opcode & 0x0F) as usize;
+        let x = self.x as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,                                   // IDL
+            0x0 => self.d = bus.read(self.r[n]),                                 // LDN
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),                        // INC
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),                        // DEC
+            0x3 => {
+                let taken = self.condition(opcode, bus);
+                self.short_branch(taken, bus);
+            }
+            0x4 => {                                                             // LDA
+                self.d = bus.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[n], self.d),                                 // STR
+            0x6 => match n {
+                0 => self.r[x] = self.r[x].wrapping_add(1),                      // IRX
+                1..=7 => {                                                       // OUT
+                    let value = bus.read(self.r[x]);
+                    bus.output(n as u8, value);
+                    self.r[x] = self.r[x].wrapping_add(1);
+                }
+                8 => (),                                                         // Not used on the 1802
+                _ => {                                                           // INP
+                    let value = bus.input(n as u8 - 8);
+                    bus.write(self.r[x], value);
+                    self.d = value;
+                }
+            },
+            0x7 => self.op_7n(n, bus),
+            0x8 => self.d = self.r[n] as u8,                                     // GLO
+            0x9 => self.d = (self.r[n] >> 8) as u8,                              // GHI
+            0xA => self.r[n] = self.r[n] & 0xFF00 | self.d as u16,               // PLO
+            0xB => self.r[n] = self.r[n] & 0x00FF | (self.d as u16) << 8,        // PHI
+            0xC => {
+                self.long_branch_or_skip(opcode, bus);
+                return 3;
+            }
+            0xD => self.p = n as u8,                                             // SEP
+            0xE => self.x = n as u8,                                             // SEX
+            _ => self.op_fn(n, bus),
+        }
+        2
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let pc = self.p as usize;
+        let value = bus.read(self.r[pc]);
+        self.r[pc] = self.r[pc].wrapping_add(1);
+        value
+    }
+
+    // Condition tested by a short branch (3N) or long branch (CN) opcode, before any inversion.
+    // The low 3 bits pick the condition and bit 3 inverts it
+    fn condition(&mut self, opcode: u8, bus: &mut impl Bus) -> bool {
+        let condition = match opcode & 0x07 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => bus.flag(flag - 3),
+        };
+        condition != (opcode & 0x08 != 0)
+    }
+
+    // 3N: the branch address is the next byte, replacing the low byte of the program counter
+    fn short_branch(&mut self, taken: bool, bus: &mut impl Bus) {
+        let pc = self.p as usize;
+        if taken {
+            let low = bus.read(self.r[pc]);
+            self.r[pc] = self.r[pc] & 0xFF00 | low as u16;
+        } else {
+            self.r[pc] = self.r[pc].wrapping_add(1);
+        }
+    }
+
+    // CN: long branches jump to the next two bytes, long skips step over them
+    fn long_branch_or_skip(&mut self, opcode: u8, bus: &mut impl Bus) {
+        let pc = self.p as usize;
+        let condition = |cpu: &Self| match opcode & 0x03 {
+            0 => true,
+            1 => cpu.q,
+            2 => cpu.d == 0,
+            _ => cpu.df,
+        };
+        match opcode {
+            // LBR, LBQ, LBZ, LBDF and LSKP (which never branches), then the inverted LBNQ, LBNZ, LBNF
+            0xC0..=0xC3 | 0xC8..=0xCB => {
+                let taken = opcode != 0xC8 && (condition(self) != (opcode & 0x08 != 0));
+                if taken {
+                    let high = bus.read(self.r[pc]);
+                    let low = bus.read(self.r[pc].wrapping_add(1));
+                    self.r[pc] = (high as u16) << 8 | low as u16;
+                } else {
+                    self.r[pc] = self.r[pc].wrapping_add(2);
+                }
+            }
+            0xC4 => (),                                                          // NOP
+            // LSNQ, LSNZ, LSNF skip when the condition is false, LSIE, LSQ, LSZ, LSDF when it is true
+            _ => {
+                let skip = match opcode {
+                    0xC5..=0xC7 => !condition(self),
+                    0xCC => self.ie,
+                    _ => condition(self),
+                };
+                if skip {
+                    self.r[pc] = self.r[pc].wrapping_add(2);
+                }
+            }
+        }
+    }
+
+    fn op_7n(&mut self, n: usize, bus: &mut impl Bus) {
+        let x = self.x as usize;
+        match n {
+            0x0 | 0x1 => {                                                       // RET, DIS
+                let value = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0x0F;
+                self.ie = n == 0;
+            }
+            0x2 => {                                                             // LDXA
+                self.d = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            0x3 => {                                                             // STXD
+                bus.write(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x4 => self.add(bus.read(self.r[x]), self.df),                       // ADC
+            0x5 => self.subtract(bus.read(self.r[x]), self.d, self.df),          // SDB
+            0x6 => {                                                             // SHRC
+                let carry = self.d & 0x01 != 0;
+                self.d = self.d >> 1 | u8::from(self.df) << 7;
+                self.df = carry;
+            }
+            0x7 => self.subtract(self.d, bus.read(self.r[x]), self.df),          // SMB
+            0x8 => bus.write(self.r[x], self.t),                                 // SAV
+            0x9 => {                                                             // MARK
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,                                               // REQ
+            0xB => self.q = true,                                                // SEQ
+            0xC => {                                                             // ADCI
+                let value = self.fetch(bus);
+                self.add(value, self.df);
+            }
+            0xD => {                                                             // SDBI
+                let value = self.fetch(bus);
+                self.subtract(value, self.d, self.df);
+            }
+            0xE => {                                                             // SHLC
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | u8::from(self.df);
+                self.df = carry;
+            }
+            _ => {                                                               // SMBI
+                let value = self.fetch(bus);
+                self.subtract(self.d, value, self.df);
+            }
+        }
+    }
+
+    fn op_fn(&mut self, n: usize, bus: &mut impl Bus) {
+        // F0-F7 take their operand from M(R(X)), F8-FF from the byte after the opcode
+        let operand = match n {
+            0x6 | 0xE => 0, // Shifts have no operand
+            0x0..=0x7 => bus.read(self.r[self.x as usize]),
+            _ => self.fetch(bus),
+        };
+        match n & 0x7 {
+            0x0 => self.d = operand,                                             // LDX, LDI
+            0x1 => self.d |= operand,                                            // OR, ORI
+            0x2 => self.d &= operand,                                            // AND, ANI
+            0x3 => self.d ^= operand,                                            // XOR, XRI
+            0x4 => self.add(operand, false),                                     // ADD, ADI
+            0x5 => self.subtract(operand, self.d, true),                         // SD, SDI
+            0x6 if n == 0x6 => {                                                 // SHR
+                self.df = self.d & 0x01 != 0;
+                self.d >>= 1;
+            }
+            0x6 => {                                                             // SHL
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => self.subtract(self.d, operand, true),                           // SM, SMI
+        }
+    }
+
+    // D = D + value + carry, DF is the carry out
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // D = minuend - subtrahend, borrowing if `no_borrow` is false. DF is set when there was no borrow
+    fn subtract(&mut self, minuend: u8, subtrahend: u8, no_borrow: bool) {
+        let difference = minuend as i16 - subtrahend as i16 - i16::from(!no_borrow);
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 64K of RAM with the EF flags, an input port value and the outputs seen
+    struct TestBus {
+        memory: Vec<u8>,
+        flags: [bool; 5],
+        input: u8,
+        outputs: Vec<(u8, u8)>,
+    }
+
+    impl Bus for TestBus {
+        fn read(&mut self, address: u16) -> u8 {
+            self.memory[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory[address as usize] = value;
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.outputs.push((port, value));
+        }
+
+        fn input(&mut self, _port: u8) -> u8 {
+            self.input
+        }
+
+        fn flag(&mut self, flag: u8) -> bool {
+            self.flags[flag as usize]
+        }
+    }
+
+    // A CPU fresh from reset, running from 0x0000 with `program` there
+    fn machine(program: &[u8]) -> (Cdp1802, TestBus) {
+        let mut bus = TestBus { memory: vec![0; 0x10000], flags: [false; 5], input: 0, outputs: Vec::new() };
+        bus.memory[..program.len()].copy_from_slice(program);
+        (Cdp1802::new(), bus)
+    }
+
+    // Run `count` instructions, checking each took `cycles` machine cycles
+    fn run(cpu: &mut Cdp1802, bus: &mut TestBus, count: usize, cycles: u32) {
+        for _ in 0..count {
+            assert_eq!(cpu.step(bus), cycles);
+        }
+    }
+
+    #[test]
+    fn register_ops() {
+        let (mut cpu, mut bus) = machine(&[
+            0xF8, 0x12, 0xB5, 0xF8, 0x34, 0xA5, // LDI 12, PHI 5, LDI 34, PLO 5
+            0x15, 0x26, 0x85, 0x95, 0xE6,       // INC 5, DEC 6, GLO 5, GHI 5, SEX 6
+            0xF8, 0x99, 0xA7, 0xF8, 0x5A, 0x57, // R7 = 0x0099, STR 7 of 5A
+            0xF8, 0x00, 0x07, 0x47,             // LDI 0, LDN 7, LDA 7
+        ]);
+        run(&mut cpu, &mut bus, 4, 2);
+        assert_eq!(cpu.r[5], 0x1234);
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.r[5], cpu.r[6]), (0x1235, 0xFFFF));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!(cpu.d, 0x35);
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!(cpu.d, 0x12);
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!(cpu.x, 6);
+        run(&mut cpu, &mut bus, 4, 2);
+        assert_eq!(bus.memory[0x99], 0x5A);
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.d, cpu.r[7]), (0x5A, 0x0099));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, cpu.r[7]), (0x5A, 0x009A));
+        assert_eq!(cpu.r[0], 0x15);
+    }
+
+    #[test]
+    fn stack_and_io() {
+        let (mut cpu, mut bus) = machine(&[
+            0xF8, 0x05, 0xB2, 0xE2,             // R2 = 0x0500, SEX 2
+            0x63, 0x6C,                         // OUT 3, INP 4
+            0xF8, 0x11, 0x73, 0x72,             // LDI 11, STXD, LDXA
+            0x7B, 0x7A,                         // SEQ, REQ
+        ]);
+        bus.memory[0x500] = 0x42;
+        bus.input = 0x77;
+        run(&mut cpu, &mut bus, 4, 2);
+        assert_eq!(bus.outputs, [(3, 0x42)]);
+        assert_eq!(cpu.r[2], 0x501);
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, bus.memory[0x501]), (0x77, 0x77));
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((bus.memory[0x501], cpu.r[2]), (0x11, 0x500));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, cpu.r[2]), (0x42, 0x501));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert!(cpu.q);
+        run(&mut cpu, &mut bus, 1, 2);
+        assert!(!cpu.q);
+    }
+
+    #[test]
+    fn arithmetic_flags() {
+        let (mut cpu, mut bus) = machine(&[
+            0xF8, 0xF0, 0xFC, 0x20,             // LDI F0, ADI 20: carry out
+            0x7C, 0x01,                         // ADCI 1: adds the carry
+            0xF8, 0x05, 0xFF, 0x07,             // LDI 5, SMI 7: borrow
+            0xF8, 0x07, 0xFF, 0x05,             // LDI 7, SMI 5: no borrow
+            0xF8, 0x05, 0xFD, 0x07,             // LDI 5, SDI 7
+            0xF8, 0x00, 0xFF, 0x00,             // LDI 0, SMI 0: DF set
+            0x7F, 0x01, 0x7F, 0x01,             // SMBI 1 twice, borrowing on the second
+            0xF8, 0x81, 0xF6, 0x76,             // LDI 81, SHR, SHRC
+            0xFE, 0x7E,                         // SHL, SHLC
+        ]);
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.d, cpu.df), (0x10, true));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, cpu.df), (0x12, false));
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.d, cpu.df), (0xFE, false));
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.d, cpu.df), (0x02, true));
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.d, cpu.df), (0x02, true));
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.d, cpu.df), (0x00, true));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, cpu.df), (0xFF, false));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, cpu.df), (0xFD, true));
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.d, cpu.df), (0x40, true));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, cpu.df), (0xA0, false));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, cpu.df), (0x40, true));
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((cpu.d, cpu.df), (0x81, false));
+    }
+
+    #[test]
+    fn short_branches() {
+        let mut program = vec![0; 0x30];
+        program[0x00..0x02].copy_from_slice(&[0x30, 0x05]);              // BR 05
+        program[0x05..0x09].copy_from_slice(&[0xF8, 0x00, 0x32, 0x20]);  // LDI 0, BZ 20
+        program[0x20..0x26].copy_from_slice(&[0x3A, 0x2A, 0x34, 0x2A, 0x34, 0x2A]); // BNZ 2A, B1 2A, B1 2A
+        let (mut cpu, mut bus) = machine(&program);
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!(cpu.r[0], 0x05);
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!(cpu.r[0], 0x20);
+        // Not taken, so the branch address is stepped over
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!(cpu.r[0], 0x22);
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!(cpu.r[0], 0x24);
+        bus.flags[1] = true;
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!(cpu.r[0], 0x2A);
+    }
+
+    #[test]
+    fn long_branches_and_skips() {
+        let mut program = vec![0; 0x210];
+        program[0x000..0x003].copy_from_slice(&[0xC0, 0x01, 0x00]); // LBR 0100
+        program[0x100..0x103].copy_from_slice(&[0xC2, 0x02, 0x00]); // LBZ 0200
+        program[0x200..0x20B].copy_from_slice(&[
+            0xCA, 0x03, 0x00,                   // LBNZ 0300, not taken
+            0xC8, 0x12, 0x34,                   // LSKP
+            0xCE, 0xFF, 0xFF,                   // LSZ, taken
+            0xC6,                               // LSNZ, not taken
+            0xC4,                               // NOP
+        ]);
+        let (mut cpu, mut bus) = machine(&program);
+        run(&mut cpu, &mut bus, 1, 3);
+        assert_eq!(cpu.r[0], 0x100);
+        run(&mut cpu, &mut bus, 1, 3);
+        assert_eq!(cpu.r[0], 0x200);
+        run(&mut cpu, &mut bus, 1, 3);
+        assert_eq!(cpu.r[0], 0x203);
+        run(&mut cpu, &mut bus, 1, 3);
+        assert_eq!(cpu.r[0], 0x206);
+        run(&mut cpu, &mut bus, 1, 3);
+        assert_eq!(cpu.r[0], 0x209);
+        run(&mut cpu, &mut bus, 1, 3);
+        assert_eq!(cpu.r[0], 0x20A);
+        run(&mut cpu, &mut bus, 1, 3);
+        assert_eq!(cpu.r[0], 0x20B);
+    }
+
+    #[test]
+    fn interrupts() {
+        let (mut cpu, mut bus) = machine(&[]);
+        cpu.x = 5;
+        cpu.p = 3;
+        assert_eq!(cpu.interrupt(), 1);
+        assert_eq!((cpu.t, cpu.x, cpu.p, cpu.ie), (0x53, 2, 1, false));
+        // A second request is ignored until interrupts are enabled again
+        assert_eq!(cpu.interrupt(), 0);
+        assert_eq!((cpu.x, cpu.p), (2, 1));
+
+        // SAV and RET go back to where the interrupt came in
+        cpu.r[1] = 0x300;
+        cpu.r[2] = 0x400;
+        bus.memory[0x300..0x302].copy_from_slice(&[0x78, 0x70]);
+        run(&mut cpu, &mut bus, 2, 2);
+        assert_eq!((cpu.x, cpu.p, cpu.ie, cpu.r[2]), (5, 3, true, 0x401));
+
+        // MARK pushes X and P, then SEX 2, INC 2 and DIS pop them with interrupts left off
+        cpu.r[3] = 0x310;
+        bus.memory[0x310..0x314].copy_from_slice(&[0x79, 0xE2, 0x12, 0x71]);
+        run(&mut cpu, &mut bus, 1, 2);
+        assert_eq!((bus.memory[0x401], cpu.x, cpu.r[2]), (0x53, 3, 0x400));
+        run(&mut cpu, &mut bus, 3, 2);
+        assert_eq!((cpu.x, cpu.p, cpu.ie, cpu.r[3]), (5, 3, false, 0x314));
+    }
+
+    #[test]
+    fn idle_and_dma() {
+        let (mut cpu, mut bus) = machine(&[0x00]);
+        run(&mut cpu, &mut bus, 1, 2);
+        assert!(cpu.idle);
+        // Idling takes a cycle at a time without fetching
+        run(&mut cpu, &mut bus, 3, 1);
+        assert_eq!(cpu.r[0], 0x01);
+
+        cpu.r[0] = 0x400;
+        bus.memory[0x400] = 0xAB;
+        assert_eq!(cpu.dma_out(&mut bus), 0xAB);
+        assert_eq!(cpu.r[0], 0x401);
+        assert!(!cpu.idle);
+    }
+}
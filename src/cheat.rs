@@ -0,0 +1,125 @@
+// Cheats: the classic RAM search for finding where a game keeps its lives, level or score, and cheats that
+// hold bytes of memory at a chosen value. Searching starts from a snapshot of memory with every address as a
+// candidate. Each filter compares memory with the snapshot, drops the addresses that don't match and takes
+// a new snapshot, so playing a bit between filters narrows the candidates down.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Equal(u8), // Holds this value now
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Filter {
+    fn keeps(self, previous: u8, current: u8) -> bool {
+        match self {
+            Filter::Equal(value) => current == value,
+            Filter::Changed => current != previous,
+            Filter::Unchanged => current == previous,
+            Filter::Increased => current > previous,
+            Filter::Decreased => current < previous,
+        }
+    }
+}
+
+pub struct Search {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl Search {
+    // Start a search with `memory` as the snapshot and every address as a candidate
+    pub fn new(memory: &[u8]) -> Self {
+        Search {
+            snapshot: memory.to_vec(),
+            candidates: (0..memory.len() as u16).collect(),
+        }
+    }
+
+    // Keep the candidates that match, then snapshot `memory` for the next filter
+    pub fn filter(&mut self, memory: &[u8], filter: Filter) {
+        self.candidates.retain(|&address| filter.keeps(self.snapshot[address as usize], memory[address as usize]));
+        self.snapshot.copy_from_slice(memory);
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    // Value of `address` when the last filter ran
+    pub fn previous(&self, address: u16) -> u8 {
+        self.snapshot[address as usize]
+    }
+}
+
+// A byte written back every frame, so whatever the game does it keeps this value
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cheat {
+    pub address: u16,
+    pub value: u8,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+// Write the enabled cheats into memory
+pub fn apply(cheats: &[Cheat], memory: &mut [u8]) {
+    for cheat in cheats.iter().filter(|cheat| cheat.enabled) {
+        if let Some(byte) = memory.get_mut(cheat.address as usize) {
+            *byte = cheat.value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Candidates left after one filter, with memory going from `before` to `after`
+    fn filtered(before: &[u8], after: &[u8], filter: Filter) -> Vec<u16> {
+        let mut search = Search::new(before);
+        search.filter(after, filter);
+        search.candidates().to_vec()
+    }
+
+    #[test]
+    fn filters_compare_with_the_last_snapshot() {
+        let before = [5, 5, 5, 5];
+        let after = [4, 5, 6, 7];
+        assert_eq!(filtered(&before, &after, Filter::Equal(6)), [2]);
+        assert_eq!(filtered(&before, &after, Filter::Changed), [0, 2, 3]);
+        assert_eq!(filtered(&before, &after, Filter::Unchanged), [1]);
+        assert_eq!(filtered(&before, &after, Filter::Increased), [2, 3]);
+        assert_eq!(filtered(&before, &after, Filter::Decreased), [0]);
+    }
+
+    #[test]
+    fn filters_narrow_the_candidates_down() {
+        let mut search = Search::new(&[1, 1, 1, 1]);
+        search.filter(&[2, 2, 1, 0], Filter::Increased);
+        assert_eq!(search.candidates(), [0, 1]);
+        // Compared with the memory of the last filter, not the start of the search
+        assert_eq!(search.previous(0), 2);
+        search.filter(&[2, 3, 9, 9], Filter::Unchanged);
+        assert_eq!(search.candidates(), [0]);
+    }
+
+    #[test]
+    fn only_enabled_cheats_apply() {
+        let cheats = [
+            Cheat { address: 1, value: 9, enabled: true },
+            Cheat { address: 2, value: 9, enabled: false },
+            Cheat { address: 0x1000, value: 9, enabled: true },
+        ];
+        let mut memory = [0; 4];
+        apply(&cheats, &mut memory);
+        assert_eq!(memory, [0, 9, 0, 0]);
+    }
+}
@@ -0,0 +1,223 @@
+use winit::event::VirtualKeyCode;
+
+use crate::cheat::{Cheat, Filter, Search};
+use crate::chip8::Chip8;
+use crate::display::Palette;
+use crate::memview::hex_digit;
+use crate::text::{Canvas, CHAR_WIDTH, LINE_HEIGHT};
+
+// Where the cheats list starts, in characters
+const CHEATS_COLUMN: usize = 44;
+
+// Which list the arrow keys move in
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Candidates,
+    Cheats,
+}
+
+// RAM search and cheat list. The search is kept while the finder is closed, so the game can be played
+// between filters. Typed hex digits set the value for the equal filter, or the value of the selected cheat
+pub struct CheatFinder {
+    search: Option<Search>,
+    focus: Focus,
+    candidate: usize,    // Selected candidate
+    cheat: usize,        // Selected cheat
+    scroll: usize,       // First candidate shown
+    cheat_scroll: usize, // First cheat shown
+    value: u8,           // Compared with by the equal filter
+    pending: Option<u8>, // High nibble typed so far
+}
+
+impl CheatFinder {
+    pub fn new() -> Self {
+        CheatFinder {
+            search: None,
+            focus: Focus::Candidates,
+            candidate: 0,
+            cheat: 0,
+            scroll: 0,
+            cheat_scroll: 0,
+            value: 0,
+            pending: None,
+        }
+    }
+
+    // Keys the finder uses. The rest still reach the hotkeys, but the game gets no keypad input while it is open
+    pub fn handles(key: VirtualKeyCode) -> bool {
+        hex_digit(key).is_some() || matches!(
+            key,
+            VirtualKeyCode::Up | VirtualKeyCode::Down | VirtualKeyCode::PageUp | VirtualKeyCode::PageDown |
+            VirtualKeyCode::Tab | VirtualKeyCode::Return | VirtualKeyCode::Space | VirtualKeyCode::Delete |
+            VirtualKeyCode::Back | VirtualKeyCode::S | VirtualKeyCode::Equals | VirtualKeyCode::Period |
+            VirtualKeyCode::Comma | VirtualKeyCode::X | VirtualKeyCode::U
+        )
+    }
+
+    // Returns whether the cheats changed and need saving
+    pub fn key_pressed(&mut self, key: VirtualKeyCode, chip8: &mut Chip8) -> bool {
+        let filter = match key {
+            VirtualKeyCode::Equals => Some(Filter::Equal(self.value)),
+            VirtualKeyCode::Period => Some(Filter::Increased),
+            VirtualKeyCode::Comma => Some(Filter::Decreased),
+            VirtualKeyCode::X => Some(Filter::Changed),
+            VirtualKeyCode::U => Some(Filter::Unchanged),
+            _ => None,
+        };
+        if let Some(filter) = filter {
+            if let Some(search) = self.search.as_mut() {
+                search.filter(&chip8.memory, filter);
+            }
+            self.candidate = 0;
+            self.pending = None;
+            return false;
+        }
+
+        let page = 16;
+        let (cursor, count) = match self.focus {
+            Focus::Candidates => (&mut self.candidate, self.search.as_ref().map_or(0, |search| search.candidates().len())),
+            Focus::Cheats => (&mut self.cheat, chip8.cheats.len()),
+        };
+        let before = *cursor;
+        match key {
+            VirtualKeyCode::Up => *cursor = cursor.saturating_sub(1),
+            VirtualKeyCode::Down => *cursor += 1,
+            VirtualKeyCode::PageUp => *cursor = cursor.saturating_sub(page),
+            VirtualKeyCode::PageDown => *cursor += page,
+            _ => (),
+        }
+        *cursor = (*cursor).min(count.saturating_sub(1));
+        if *cursor != before {
+            self.pending = None;
+        }
+
+        match key {
+            VirtualKeyCode::S => {
+                self.search = Some(Search::new(&chip8.memory));
+                self.candidate = 0;
+                false
+            }
+            VirtualKeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Candidates => Focus::Cheats,
+                    Focus::Cheats => Focus::Candidates,
+                };
+                self.pending = None;
+                false
+            }
+            // Enter makes a cheat holding the selected candidate at its current value
+            VirtualKeyCode::Return if self.focus == Focus::Candidates => {
+                let Some(&address) = self.search.as_ref().and_then(|search| search.candidates().get(self.candidate)) else {
+                    return false;
+                };
+                if chip8.cheats.iter().any(|cheat| cheat.address == address) {
+                    return false;
+                }
+                chip8.cheats.push(Cheat { address, value: chip8.memory[address as usize], enabled: true });
+                true
+            }
+            VirtualKeyCode::Space if self.focus == Focus::Cheats => match chip8.cheats.get_mut(self.cheat) {
+                Some(cheat) => {
+                    cheat.enabled = !cheat.enabled;
+                    true
+                }
+                None => false,
+            },
+            VirtualKeyCode::Delete | VirtualKeyCode::Back if self.focus == Focus::Cheats && self.cheat < chip8.cheats.len() => {
+                chip8.cheats.remove(self.cheat);
+                self.cheat = self.cheat.min(chip8.cheats.len().saturating_sub(1));
+                true
+            }
+            _ => match hex_digit(key) {
+                Some(digit) => self.type_digit(digit, chip8),
+                None => false,
+            },
+        }
+    }
+
+    // Two hex digits make a byte
+    fn type_digit(&mut self, digit: u8, chip8: &mut Chip8) -> bool {
+        let Some(high) = self.pending.take() else {
+            self.pending = Some(digit);
+            return false;
+        };
+        let value = high << 4 | digit;
+        match self.focus {
+            Focus::Candidates => {
+                self.value = value;
+                false
+            }
+            Focus::Cheats => match chip8.cheats.get_mut(self.cheat) {
+                Some(cheat) => {
+                    cheat.value = value;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, palette: &Palette, chip8: &Chip8) {
+        let (width, height) = (canvas.width(), canvas.height());
+        canvas.fill_rect(0, 0, width, height, palette.off);
+
+        let typed = |value: String| match self.pending {
+            Some(high) => format!("{:x}_", high),
+            None => value,
+        };
+        let header = match &self.search {
+            Some(search) => format!("Cheat search: {} candidates", search.candidates().len()),
+            None => String::from("Cheat search: press S to start"),
+        };
+        canvas.draw_text(CHAR_WIDTH, LINE_HEIGHT / 2, &header, palette.on);
+        let value = if self.focus == Focus::Candidates { typed(format!("{:02x}", self.value)) } else { format!("{:02x}", self.value) };
+        canvas.draw_text(CHAR_WIDTH * CHEATS_COLUMN, LINE_HEIGHT / 2, &format!("Value: {}", value), palette.on);
+        let filters = "=: equal to value  .: increased  ,: decreased  X: changed  U: unchanged";
+        canvas.draw_text(CHAR_WIDTH, LINE_HEIGHT * 3 / 2, filters, palette.on);
+        let footer = "S: new search  Tab: switch list  Enter: add  Space: on/off  Del: remove  F11: close";
+        canvas.draw_text(CHAR_WIDTH, height - LINE_HEIGHT, footer, palette.on);
+
+        let top = LINE_HEIGHT * 4;
+        let rows = (height - top - LINE_HEIGHT * 2) / LINE_HEIGHT;
+
+        // Candidates on the left, as `0x2f0  03  was 04`
+        if let Some(search) = &self.search {
+            scroll_to(self.candidate, &mut self.scroll, rows);
+            for (row, &address) in search.candidates().iter().skip(self.scroll).take(rows).enumerate() {
+                let y = top + row * LINE_HEIGHT;
+                let text = format!("{:#05x}  {:02x}  was {:02x}", address, chip8.memory[address as usize], search.previous(address));
+                self.draw_row(canvas, palette, CHAR_WIDTH, y, &text, self.focus == Focus::Candidates && self.scroll + row == self.candidate);
+            }
+        }
+
+        // Cheats on the right, as `[x] 0x2f0 = 03`
+        let x = CHAR_WIDTH * CHEATS_COLUMN;
+        canvas.draw_text(x, top - LINE_HEIGHT, "Cheats", palette.on);
+        scroll_to(self.cheat, &mut self.cheat_scroll, rows);
+        for (row, cheat) in chip8.cheats.iter().skip(self.cheat_scroll).take(rows).enumerate() {
+            let selected = self.focus == Focus::Cheats && self.cheat_scroll + row == self.cheat;
+            let value = if selected { typed(format!("{:02x}", cheat.value)) } else { format!("{:02x}", cheat.value) };
+            let text = format!("[{}] {:#05x} = {}", if cheat.enabled { 'x' } else { ' ' }, cheat.address, value);
+            self.draw_row(canvas, palette, x, top + row * LINE_HEIGHT, &text, selected);
+        }
+    }
+
+    // The selected row is drawn inverted
+    fn draw_row(&self, canvas: &mut Canvas, palette: &Palette, x: usize, y: usize, text: &str, selected: bool) {
+        if selected {
+            canvas.fill_rect(x - 1, y - 1, text.len() * CHAR_WIDTH + 1, LINE_HEIGHT, palette.on);
+            canvas.draw_text(x, y, text, palette.off);
+        } else {
+            canvas.draw_text(x, y, text, palette.on);
+        }
+    }
+}
+
+// Move `scroll`, the first of `rows` rows shown, just far enough that `cursor` is on screen
+fn scroll_to(cursor: usize, scroll: &mut usize, rows: usize) {
+    if cursor < *scroll {
+        *scroll = cursor;
+    } else if cursor >= *scroll + rows {
+        *scroll = cursor + 1 - rows;
+    }
+}
@@ -1,11 +1,21 @@
 // Reference: https://multigesture.net/articles/how-to-write-an-emulator-chip-8-interpreter/, https://tobiasvl.github.io/blog/write-a-chip-8-emulator/
 
-use rand::Rng;
+use std::collections::BTreeSet;
+use std::str::FromStr;
 
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::cheat::{self, Cheat};
+use crate::display::{HEIGHT, WIDTH};
 use crate::timer::Timer;
+use crate::timing::{self, Timing};
+use crate::vip::Vip;
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
+pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200; // Programs start at 0x200 per original chip-8
+pub const DEFAULT_TICKRATE: u32 = 12; // Instructions per frame for ROMs that don't say otherwise
+pub const MAX_PC: u16 = 0xFFE; // Last address a whole instruction can be fetched from
 const FONTSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -25,6 +35,144 @@ const FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+// How ambiguous instructions behave, as they differ between the original interpreter and later ones.
+// See https://github.com/chip-8/chip-8-database for the meaning of each quirk
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift: bool,              // 8XY6/8XYE shift VX in place instead of VY into VX
+    pub memory: MemoryQuirk,      // How FX55/FX65 leave I
+    pub wrap: bool,               // Sprites wrap around the screen edges instead of being clipped
+    pub jump: bool,               // BNNN jumps to XNN + VX instead of NNN + V0
+    pub vblank: bool,             // DXYN waits for the next frame, so only one sprite is drawn per frame
+    pub logic: bool,              // 8XY1/8XY2/8XY3 reset VF to 0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryQuirk {
+    IncrementByXPlusOne,
+    IncrementByX,
+    Unchanged,
+}
+
+impl Quirks {
+    // COSMAC VIP interpreter
+    pub const ORIGINAL: Quirks = Quirks {
+        shift: false,
+        memory: MemoryQuirk::IncrementByXPlusOne,
+        wrap: false,
+        jump: false,
+        vblank: true,
+        logic: true,
+    };
+    // What most modern programs written for "plain" CHIP-8 expect
+    pub const MODERN: Quirks = Quirks {
+        shift: false,
+        memory: MemoryQuirk::IncrementByXPlusOne,
+        wrap: false,
+        jump: false,
+        vblank: false,
+        logic: false,
+    };
+    // CHIP-48 on the HP48
+    pub const CHIP48: Quirks = Quirks {
+        shift: true,
+        memory: MemoryQuirk::IncrementByX,
+        wrap: false,
+        jump: true,
+        vblank: false,
+        logic: false,
+    };
+    // SUPER-CHIP 1.1
+    pub const SUPERCHIP: Quirks = Quirks {
+        shift: true,
+        memory: MemoryQuirk::Unchanged,
+        wrap: false,
+        jump: true,
+        vblank: false,
+        logic: false,
+    };
+    // XO-CHIP
+    pub const XOCHIP: Quirks = Quirks {
+        shift: false,
+        memory: MemoryQuirk::IncrementByXPlusOne,
+        wrap: true,
+        jump: false,
+        vblank: false,
+        logic: false,
+    };
+}
+
+// Quirk profiles that can be picked by name, in the order F3 cycles through them
+pub const QUIRK_PROFILES: [(&str, Quirks); 5] = [
+    ("original", Quirks::ORIGINAL),
+    ("modern", Quirks::MODERN),
+    ("chip48", Quirks::CHIP48),
+    ("superchip", Quirks::SUPERCHIP),
+    ("xochip", Quirks::XOCHIP),
+];
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::SUPERCHIP
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        let name = match name.as_str() {
+            "vip" => "original",
+            "schip" => "superchip",
+            name => name,
+        };
+        match QUIRK_PROFILES.iter().find(|(profile, _)| *profile == name) {
+            Some((_, quirks)) => Ok(*quirks),
+            None => Err(format!("unknown quirk profile '{}', expected original, modern, chip48, superchip or xochip", s)),
+        }
+    }
+}
+
+// Everything a running program can change, for saving and restoring the machine, including where the random
+// number generator is so CXNN gives the same numbers after a restore. Settings such as the quirks stay as they are
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub memory: Vec<u8>,
+    pub v_register: [u8; 16],
+    pub i_register: u16,
+    pub pc: u16,
+    pub stack: [u16; 16],
+    pub stack_ptr: u16,
+    pub screen: Vec<bool>,
+    pub key: [bool; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub rpl_flags: [u8; 16],
+    pub cycle_credit: i64,
+    pub rng_seed: [u8; 32], // Where the CXNN random number generator started and how far it has got
+    pub rng_position: u64,
+}
+
+// Callbacks from inside `tick` and `step_frame`, used by scripts. `exec_hooks` and `write_hooks` on the machine pick
+// the addresses `exec` and `write` are called for. Only the frame hook is called on the VIP
+pub trait Hooks: Send {
+    // At the end of every frame
+    fn frame(&mut self, chip8: &mut Chip8);
+    // Before the instruction at `address` runs
+    fn exec(&mut self, chip8: &mut Chip8, address: u16);
+    // After an instruction stored `value` at `address`
+    fn write(&mut self, chip8: &mut Chip8, address: u16, value: u8);
+    // Lines of text to show over the game
+    fn overlay(&self) -> Vec<String> {
+        Vec::new()
+    }
+    // The status to exit with once the hooks have asked the frontend to stop
+    fn quit(&self) -> Option<i32> {
+        None
+    }
+}
+
 pub struct Chip8 {
     pub opcode: u16,
     pub memory: [u8; 4096],
@@ -37,66 +185,347 @@ pub struct Chip8 {
     pub key: [bool; 16],
     pub draw_flag: bool,
     pub timer: Timer,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub rpl_flags: [u8; 16], // SUPER-CHIP RPL user flags, kept across resets like the HP48 kept them
+    pub frames: u64,       // Frames emulated since the machine was created
+    pub instructions: u64, // Instructions executed since the machine was created
+    pub breakpoints: BTreeSet<u16>, // Addresses where running frames pause, set by the debugger
+    pub exec_hooks: BTreeSet<u16>,  // Addresses that call `Hooks::exec` before running
+    pub write_hooks: BTreeSet<u16>, // Addresses that call `Hooks::write` when stored to
+    pub cheats: Vec<Cheat>,         // Bytes held at a value, written back at the end of every frame
+    rom: Vec<u8>,
+    load_address: u16,
+    paused: bool,
+    step_off: bool,        // Run the instruction at pc even if it has a breakpoint, set when resuming
+    cycle_credit: i64,     // VIP machine cycles left in this frame, negative if the last frame overran
+    rng: ChaCha12Rng, // What StdRng is, named so its position can be saved in snapshots
+    written: [bool; 4096], // Addresses stored to since the last `take_writes`
+    vip: Option<Box<Vip>>, // Run ROMs on an emulated COSMAC VIP instead of interpreting them here
+    hooks: Option<Box<dyn Hooks>>,
+    hooked_writes: Vec<(u16, u8)>, // Stores to `write_hooks` addresses by the current instruction
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Self {
-        let mut timer = Timer::new();
-        timer.start();
-
         let mut new_chip8 = Chip8 {
             opcode: 0,
             memory: [0; 4096],
             v_register: [0; 16],
             i_register: 0,
-            pc: 0x200,         // start at 0x200 per original chip-8
+            pc: DEFAULT_LOAD_ADDRESS,
             screen: [false; WIDTH * HEIGHT],
             stack: [0; 16],
             stack_ptr: 0,
             key: [false; 16],
             draw_flag: false,
-            timer,
+            timer: Timer::new(),
+            quirks: Quirks::default(),
+            timing: Timing::default(),
+            rpl_flags: [0; 16],
+            frames: 0,
+            instructions: 0,
+            breakpoints: BTreeSet::new(),
+            exec_hooks: BTreeSet::new(),
+            write_hooks: BTreeSet::new(),
+            cheats: Vec::new(),
+            rom: Vec::new(),
+            load_address: DEFAULT_LOAD_ADDRESS,
+            paused: false,
+            step_off: false,
+            cycle_credit: 0,
+            rng: ChaCha12Rng::from_entropy(),
+            written: [false; 4096],
+            vip: None,
+            hooks: None,
+            hooked_writes: Vec::new(),
         };
         new_chip8.memory[0x050..=0x09F].copy_from_slice(&FONTSET);
         new_chip8
     }
 
-    // Reset everything to original state
-    pub fn _reset(&mut self) {
+    // Reset everything to original state and reload the ROM, as if the machine was switched off and on
+    pub fn reset(&mut self) {
         self.opcode = 0;
         self.memory = [0; 4096];
         self.v_register = [0; 16];
         self.i_register = 0;
-        self.pc = 0x200;        // start at 0x200 per original chip-8
+        self.pc = self.load_address;
         self.screen = [false; WIDTH * HEIGHT];
         self.stack = [0; 16];
         self.stack_ptr = 0;
         self.key = [false; 16];
         self.draw_flag = false;
-        self.memory[0x050..0x09F].copy_from_slice(&FONTSET);
-        self.timer = Timer::new();
+        match &mut self.vip {
+            Some(vip) => vip.reset(&mut self.memory),
+            None => self.memory[0x050..=0x09F].copy_from_slice(&FONTSET),
+        }
+        let start = self.load_address as usize;
+        self.memory[start..(start + self.rom.len())].copy_from_slice(&self.rom);
+        self.timer.set_dt(0);
+        self.timer.set_st(0);
+        self.written = [false; 4096];
+        self.cycle_credit = 0;
+    }
+
+    // Check that a ROM fits in memory after the load address
+    pub fn check_rom(&self, data: &[u8]) -> Result<(), String> {
+        let max_size = self.memory.len().saturating_sub(self.load_address as usize);
+        if data.len() > max_size {
+            return Err(format!("ROM is too large ({} bytes, at most {} fit in memory)", data.len(), max_size));
+        }
+        Ok(())
+    }
+
+    // Set where ROMs are loaded and execution starts, takes effect on the next load
+    pub fn set_load_address(&mut self, address: u16) {
+        self.load_address = address;
+    }
+
+    // Run ROMs on the VIP's own interpreter from now on, takes effect on the next load
+    pub fn attach_vip(&mut self, vip: Vip) {
+        self.vip = Some(Box::new(vip));
+    }
+
+    pub fn has_vip(&self) -> bool {
+        self.vip.is_some()
+    }
+
+    // Call these hooks while running frames from now on
+    pub fn set_hooks(&mut self, hooks: Box<dyn Hooks>) {
+        self.hooks = Some(hooks);
     }
 
-    // Load data into memory
-    pub fn load(&mut self, data: &[u8]) {
-        self.memory[0x200..(0x200 + data.len())].copy_from_slice(data);
+    // Text the hooks want shown over the game
+    pub fn overlay(&self) -> Vec<String> {
+        self.hooks.as_ref().map_or_else(Vec::new, |hooks| hooks.overlay())
     }
 
-    // Emulate one cycle
-    pub fn tick(&mut self) {
+    // Set when the hooks ask to exit, with the status to exit with. The machine is paused by then
+    pub fn quit_requested(&self) -> Option<i32> {
+        self.hooks.as_ref().and_then(|hooks| hooks.quit())
+    }
+
+    // The hooks are taken out while they run so they can be handed the machine
+    fn call_hooks(&mut self, call: impl FnOnce(&mut dyn Hooks, &mut Chip8)) {
+        if let Some(mut hooks) = self.hooks.take() {
+            call(hooks.as_mut(), self);
+            self.hooks = Some(hooks);
+        }
+    }
+
+    // Make CXNN deterministic
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+    }
+
+    // Load a ROM and reset the machine to start it
+    pub fn load(&mut self, data: &[u8]) -> Result<(), String> {
+        self.check_rom(data)?;
+        self.rom = data.to_vec();
+        self.reset();
+        Ok(())
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.step_off = false;
+    }
+
+    // Carry on running. A paused machine sitting on a breakpoint runs on past it
+    pub fn resume(&mut self) {
+        self.step_off = self.paused && self.breakpoints.contains(&self.pc);
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Save the machine state. The VIP's CPU state isn't covered, so there are no snapshots in VIP mode
+    pub fn snapshot(&mut self) -> Result<Snapshot, String> {
+        if self.vip.is_some() {
+            return Err(String::from("snapshots aren't supported with the VIP interpreter"));
+        }
+        Ok(Snapshot {
+            memory: self.memory.to_vec(),
+            v_register: self.v_register,
+            i_register: self.i_register,
+            pc: self.pc,
+            stack: self.stack,
+            stack_ptr: self.stack_ptr,
+            screen: self.screen.to_vec(),
+            key: self.key,
+            delay_timer: self.timer.get_dt(),
+            sound_timer: self.timer.get_st(),
+            rpl_flags: self.rpl_flags,
+            cycle_credit: self.cycle_credit,
+            rng_seed: self.rng.get_seed(),
+            rng_position: self.rng.get_word_pos() as u64, // In 32-bit words, too many to ever overflow
+        })
+    }
+
+    // Put the machine back in a saved state. Nothing changes if the snapshot doesn't fit this machine
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        if self.vip.is_some() {
+            return Err(String::from("snapshots aren't supported with the VIP interpreter"));
+        }
+        if snapshot.memory.len() != self.memory.len() || snapshot.screen.len() != self.screen.len() {
+            return Err(String::from("snapshot is from a machine with a different memory or screen size"));
+        }
+        if snapshot.stack_ptr as usize > self.stack.len() {
+            return Err(format!("snapshot has an invalid stack pointer {}", snapshot.stack_ptr));
+        }
+        // The next fetch reads two bytes at pc, and I may point at any byte
+        let fits = |address: u16| address <= MAX_PC;
+        if !fits(snapshot.pc) {
+            return Err(format!("snapshot has an invalid program counter {:#05x}", snapshot.pc));
+        }
+        if snapshot.i_register as usize >= snapshot.memory.len() {
+            return Err(format!("snapshot has an invalid index register {:#05x}", snapshot.i_register));
+        }
+        if let Some(address) = snapshot.stack[..snapshot.stack_ptr as usize].iter().find(|&&address| !fits(address)) {
+            return Err(format!("snapshot has an invalid return address {:#05x}", address));
+        }
+        self.memory.copy_from_slice(&snapshot.memory);
+        self.v_register = snapshot.v_register;
+        self.i_register = snapshot.i_register;
+        self.pc = snapshot.pc;
+        self.stack = snapshot.stack;
+        self.stack_ptr = snapshot.stack_ptr;
+        self.screen.copy_from_slice(&snapshot.screen);
+        self.key = snapshot.key;
+        self.timer.set_dt(snapshot.delay_timer);
+        self.timer.set_st(snapshot.sound_timer);
+        self.rpl_flags = snapshot.rpl_flags;
+        self.cycle_credit = snapshot.cycle_credit;
+        self.rng = ChaCha12Rng::from_seed(snapshot.rng_seed);
+        self.rng.set_word_pos(snapshot.rng_position as u128);
+        self.draw_flag = true;
+        Ok(())
+    }
+
+    // Which addresses were stored to since the last call, for the memory viewer
+    pub fn take_writes(&mut self) -> [bool; 4096] {
+        std::mem::replace(&mut self.written, [false; 4096])
+    }
+
+    // Change a byte from outside the program, such as an editor. It counts as a write like the program's own
+    pub fn poke(&mut self, address: u16, value: u8) {
+        let address = address as usize % self.memory.len();
+        self.memory[address] = value;
+        self.written[address] = true;
+    }
+
+    // Emulate one 60Hz frame unless paused. Afterwards draw_flag tells whether the screen changed
+    pub fn run_frame(&mut self, instructions: u32) {
+        if self.paused {
+            self.draw_flag = false;
+            return;
+        }
+        self.step_frame(instructions);
+    }
+
+    // Emulate one 60Hz frame worth of cycles and count the timers down, even while paused.
+    // With fixed timing a frame runs `instructions` instructions, with VIP timing it runs until the
+    // frame's machine cycles are used up. Breakpoints pause the machine before their instruction, except
+    // on the first instruction after resuming or of a frame advanced while paused
+    pub fn step_frame(&mut self, instructions: u32) {
+        if self.vip.is_some() {
+            self.step_vip_frame();
+            cheat::apply(&self.cheats, &mut self.memory);
+            self.call_hooks(|hooks, chip8| hooks.frame(chip8));
+            return;
+        }
+        let mut drawn = false;
+        let mut executed = 0;
+        self.cycle_credit += timing::VIP_FRAME_CYCLES;
+        loop {
+            let done = match self.timing {
+                Timing::Fixed => executed >= instructions,
+                Timing::Vip => self.cycle_credit <= 0,
+            };
+            if done {
+                break;
+            }
+            let step_off = std::mem::take(&mut self.step_off) || (self.paused && executed == 0);
+            if !step_off && self.breakpoints.contains(&self.pc) {
+                self.paused = true;
+                break;
+            }
+            self.cycle_credit -= self.tick() as i64;
+            executed += 1;
+            drawn |= self.draw_flag;
+            // With the vblank quirk a draw waits for the next frame, idling away the rest of this one
+            if self.quirks.vblank && self.draw_flag {
+                break;
+            }
+        }
+        // Only an overrun carries over to the next frame
+        self.cycle_credit = match self.timing {
+            Timing::Fixed => 0,
+            Timing::Vip => self.cycle_credit.min(0),
+        };
+        self.draw_flag = drawn;
+        self.timer.tick();
+        self.frames += 1;
+        cheat::apply(&self.cheats, &mut self.memory);
+        self.call_hooks(|hooks, chip8| hooks.frame(chip8));
+    }
+
+    // Emulate one frame of the VIP, then show its state as CHIP-8 registers, screen and sound timer so the
+    // rest of the emulator doesn't need to know. The sound timer is held above 0 while the speaker is on,
+    // through the tick
+    fn step_vip_frame(&mut self) {
+        let Some(vip) = &mut self.vip else { return };
+        self.instructions += vip.run_frame(&mut self.memory, &mut self.written, &self.key);
+        let screen = vip.screen();
+        self.draw_flag = screen != self.screen;
+        self.screen = screen;
+        let (pc, i, v_base) = vip.registers();
+        self.pc = pc;
+        self.i_register = i;
+        self.v_register.copy_from_slice(&self.memory[v_base..v_base + 16]);
+        self.timer.set_st(if vip.sound() { 2 } else { 0 });
+        self.timer.tick();
+        self.frames += 1;
+    }
+
+    // Emulate one cycle, returning what it would have cost on the VIP in machine cycles
+    pub fn tick(&mut self) -> u32 {
+        if self.exec_hooks.contains(&self.pc) {
+            let address = self.pc;
+            self.call_hooks(|hooks, chip8| hooks.exec(chip8, address));
+        }
         self.draw_flag = false;
         // Fetch
         self.fetch();
         // Decode
         let nibbles = self.decode();
+        let vx = self.v_register[nibbles.1 as usize];
+        let vy = self.v_register[nibbles.2 as usize];
+        let next = self.pc;
         // Execute
         self.execute(nibbles);
+        self.instructions += 1;
+        for (address, value) in std::mem::take(&mut self.hooked_writes) {
+            self.call_hooks(|hooks, chip8| hooks.write(chip8, address, value));
+        }
+        timing::vip_cycles(self.opcode, vx, vy, self.pc == next + 2, self.quirks.wrap)
     }
 
-    // Chip-8 opcode is 2 bytes long, so merge 2 bytes from memory and increment program counter by 2
+    // Chip-8 opcode is 2 bytes long, so merge 2 bytes from memory and increment program counter by 2.
+    // Addresses are 12 bits, so a program running off the end of memory carries on from the start
     fn fetch(&mut self) {
-        self.opcode = (self.memory[self.pc as usize] as u16) << 8 | (self.memory[self.pc as usize + 1] as u16);
-        self.pc += 2;
+        let pc = self.pc & 0xFFF;
+        self.opcode = (self.read_memory(pc) as u16) << 8 | (self.read_memory(pc + 1) as u16);
+        self.pc = (pc + 2) & 0xFFF;
     }
 
     // Decode into tuple of 4 nibbles
@@ -149,6 +578,8 @@ impl Chip8 {
             (0xF, _, 3, 3) => self.op_fx33(nibbles.1),
             (0xF, _, 5, 5) => self.op_fx55(nibbles.1),
             (0xF, _, 6, 5) => self.op_fx65(nibbles.1),
+            (0xF, _, 7, 5) => self.op_fx75(nibbles.1),
+            (0xF, _, 8, 5) => self.op_fx85(nibbles.1),
             (_, _, _, _) => () // Exhausted all possible opcodes
         }
     }
@@ -159,9 +590,11 @@ impl Chip8 {
         self.draw_flag = true;
     }
 
-    // Return from a subroutine
+    // Return from a subroutine. The stack is a ring of 16 entries, so returning with nothing on it or
+    // calling with it full reuses old entries instead of stopping the machine
     fn op_00ee(&mut self) {
-        self.stack_ptr -= 1;
+        let len = self.stack.len() as u16;
+        self.stack_ptr = (self.stack_ptr + len - 1) % len;
         self.pc = self.stack[self.stack_ptr as usize];
     }
 
@@ -172,29 +605,35 @@ impl Chip8 {
 
     // Call subroutine at NNN
     fn op_2nnn(&mut self, nnn: u16) {
-        self.stack[self.stack_ptr as usize] = self.pc;
-        self.stack_ptr += 1;
+        let len = self.stack.len() as u16;
+        self.stack[(self.stack_ptr % len) as usize] = self.pc;
+        self.stack_ptr = self.stack_ptr % len + 1;
         self.pc = nnn;
     }
 
+    // Step over the next instruction, wrapping round to the start of memory like fetch does
+    fn skip(&mut self) {
+        self.pc = (self.pc + 2) & 0xFFF;
+    }
+
     // Skip next instruction if VX == NN
     fn op_3xnn(&mut self, x: u16, nn: u16) {
         if self.v_register[x as usize] == nn as u8 {
-            self.pc += 2;
+            self.skip();
         }
     }
 
     // Skip next instruction if VX != NN
     fn op_4xnn(&mut self, x: u16, nn: u16) {
         if self.v_register[x as usize] != nn as u8 {
-            self.pc += 2;
+            self.skip();
         }
     }
 
     // Skip next instruction if VX == VY
     fn op_5xy0(&mut self, x: u16, y: u16) {
         if self.v_register[x as usize] == self.v_register[y as usize] {
-            self.pc += 2;
+            self.skip();
         }
     }
 
@@ -216,16 +655,25 @@ impl Chip8 {
     // Set VX to VX or VY
     fn op_8xy1(&mut self, x: u16, y: u16) {
         self.v_register[x as usize] |= self.v_register[y as usize];
+        if self.quirks.logic {
+            self.v_register[0xF] = 0;
+        }
     }
 
     // Set VX to VX and VY
     fn op_8xy2(&mut self, x: u16, y: u16) {
         self.v_register[x as usize] &= self.v_register[y as usize];
+        if self.quirks.logic {
+            self.v_register[0xF] = 0;
+        }
     }
 
     // Set VX to VX xor VY
     fn op_8xy3(&mut self, x: u16, y: u16) {
         self.v_register[x as usize] ^= self.v_register[y as usize];
+        if self.quirks.logic {
+            self.v_register[0xF] = 0;
+        }
     }
 
     // Add VY to VX. Set VF to 1 if there's carry
@@ -242,9 +690,11 @@ impl Chip8 {
         self.v_register[0xF] = if overflow { 0 } else { 1 };
     }
 
-    // Right shift VX by 1 bit. Set VF to the shifted out bit
-    //TODO This instruction is ambiguous, and only works on "modern' programs
-    fn op_8xy6(&mut self, x: u16, _y: u16) {
+    // Right shift VY (or VX with the shift quirk) by 1 bit into VX. Set VF to the shifted out bit
+    fn op_8xy6(&mut self, x: u16, y: u16) {
+        if !self.quirks.shift {
+            self.v_register[x as usize] = self.v_register[y as usize];
+        }
         let lsb = self.v_register[x as usize] & 0b00000001;
         self.v_register[x as usize] >>= 1;
         self.v_register[0xF] = lsb;
@@ -256,9 +706,11 @@ impl Chip8 {
         self.v_register[x as usize] = result;
         self.v_register[0xF] = if overflow { 0 } else { 1 };
     }
-    // Left shift VX by 1 bit. Set VF to the shifted out bit
-    //TODO This instruction is ambiguous, and only works on "modern' programs
-    fn op_8xye(&mut self, x: u16, _y: u16) {
+    // Left shift VY (or VX with the shift quirk) by 1 bit into VX. Set VF to the shifted out bit
+    fn op_8xye(&mut self, x: u16, y: u16) {
+        if !self.quirks.shift {
+            self.v_register[x as usize] = self.v_register[y as usize];
+        }
         let msb = self.v_register[x as usize] >> 7;
         self.v_register[x as usize] <<= 1;
         self.v_register[0xF] = msb;
@@ -267,7 +719,7 @@ impl Chip8 {
     // Skip next instruction if VX != VY
     fn op_9xy0(&mut self, x: u16, y: u16) {
         if self.v_register[x as usize] != self.v_register[y as usize] {
-            self.pc += 2;
+            self.skip();
         }
     }
 
@@ -276,15 +728,15 @@ impl Chip8 {
         self.i_register = nnn;
     }
 
-    // Jump to nnn, plus value in VX
-    //TODO This instruction is ambiguous, and only works on "modern' programs
+    // Jump to NNN plus V0, or to XNN plus VX with the jump quirk
     fn op_bnnn(&mut self, x: u16, nnn: u16) {
-        self.pc = nnn + self.v_register[x as usize] as u16;
+        let offset = if self.quirks.jump { self.v_register[x as usize] } else { self.v_register[0] };
+        self.pc = (nnn + offset as u16) & 0xFFF;
     }
 
     // Get random number and binary AND with NN, and put in VX
     fn op_cxnn(&mut self, x: u16, nn: u16) {
-        self.v_register[x as usize] = rand::thread_rng().gen_range(0..=255) & nn as u8;
+        self.v_register[x as usize] = self.rng.gen_range(0..=255) & nn as u8;
     }
 
     // Draw
@@ -294,14 +746,14 @@ impl Chip8 {
 
         self.v_register[0xF] = 0;
         for y_line in 0..n {
-            if (y_coord + y_line) >= 32 { break; }
-            let pixel = self.memory[(self.i_register + y_line) as usize] as u16;
+            if !self.quirks.wrap && (y_coord + y_line) >= 32 { break; }
+            let pixel = self.read_memory(self.i_register.wrapping_add(y_line)) as u16;
             for x_line in 0..8_u16 {
-                if (x_coord + x_line) >= 64 { break; }
+                if !self.quirks.wrap && (x_coord + x_line) >= 64 { break; }
                 if (pixel & (0x80 >> x_line)) != 0 {
-                    // add modulus to x,y and remove 'clipping if' to have wrapping instead
-                    let x = x_coord + x_line;// % 64;
-                    let y = y_coord + y_line;// % 32;
+                    // Without the wrap quirk pixels past the edge were clipped above
+                    let x = (x_coord + x_line) % 64;
+                    let y = (y_coord + y_line) % 32;
 
                     // Check if the pixel will be turn off
                     if self.screen[(x + (y * 64)) as usize] {
@@ -316,15 +768,15 @@ impl Chip8 {
 
     // Skip next instruction if key is pressed
     fn op_ex9e(&mut self, x: u16) {
-        if self.key[self.v_register[x as usize] as usize] {
-            self.pc += 2;
+        if self.key[(self.v_register[x as usize] & 0xF) as usize] {
+            self.skip();
         }
     }
 
     // Skip next instruction if key is not pressed
     fn op_exa1(&mut self, x: u16) {
-        if !(self.key[self.v_register[x as usize] as usize]) {
-            self.pc += 2;
+        if !(self.key[(self.v_register[x as usize] & 0xF) as usize]) {
+            self.skip();
         }
     }
 
@@ -336,7 +788,7 @@ impl Chip8 {
     // Halt all instructions until key is pressed
     fn op_fx0a(&mut self, _x: u16) {
         if self.key == [false; 16] {
-            self.pc -= 2;
+            self.pc = self.pc.wrapping_sub(2) & 0xFFF;
         }
     }
 
@@ -352,35 +804,204 @@ impl Chip8 {
 
     // Add VX to I
     fn op_fx1e(&mut self, x: u16) {
-        self.i_register += self.v_register[x as usize] as u16;
+        self.i_register = self.i_register.wrapping_add(self.v_register[x as usize] as u16);
     }
 
-    // Set I to the location of sprite address for character in VX
+    // Set I to the location of sprite address for the hex digit in the low nibble of VX
     fn op_fx29(&mut self, x: u16) {
-        self.i_register = (self.v_register[x as usize] * 5) as u16
+        self.i_register = (self.v_register[x as usize] & 0xF) as u16 * 5
     }
 
     // Store binary-coded decimal of VX, with hundredth digit at memory location I, tenth at I+1, ones at I+2.
     // Ex. If VX is 123, address I would be 1, address I+2 would be 2, address I+3 would be 3.
     fn op_fx33(&mut self, x: u16) {
-        self.memory[self.i_register as usize] = self.v_register[x as usize] / 100;
-        self.memory[(self.i_register + 1) as usize] = self.v_register[x as usize] % 100 / 10;
-        self.memory[(self.i_register + 2) as usize] = self.v_register[x as usize] % 10;
+        self.write_memory(self.i_register, self.v_register[x as usize] / 100);
+        self.write_memory(self.i_register.wrapping_add(1), self.v_register[x as usize] % 100 / 10);
+        self.write_memory(self.i_register.wrapping_add(2), self.v_register[x as usize] % 10);
     }
 
     // Store registers to memory
-    //TODO This instruction is ambiguous, and only works on "modern' programs
     fn op_fx55(&mut self, x: u16) {
         for val in 0..=x {
-            self.memory[(self.i_register + val) as usize] = self.v_register[val as usize];
+            self.write_memory(self.i_register.wrapping_add(val), self.v_register[val as usize]);
         }
+        self.increment_i(x);
     }
 
     // Load value from memory to registers
-    //TODO This instruction is ambiguous, and only works on "modern' programs
     fn op_fx65(&mut self, x: u16) {
         for val in 0..=x {
-            self.v_register[val as usize] = self.memory[(self.i_register + val) as usize];
+            self.v_register[val as usize] = self.read_memory(self.i_register.wrapping_add(val));
+        }
+        self.increment_i(x);
+    }
+
+    // Store V0 to VX in the RPL user flags. SUPER-CHIP only has 8 flags, XO-CHIP extends them to 16
+    fn op_fx75(&mut self, x: u16) {
+        let count = x as usize + 1;
+        self.rpl_flags[..count].copy_from_slice(&self.v_register[..count]);
+    }
+
+    // Load V0 to VX from the RPL user flags
+    fn op_fx85(&mut self, x: u16) {
+        let count = x as usize + 1;
+        self.v_register[..count].copy_from_slice(&self.rpl_flags[..count]);
+    }
+
+    // Move I past the registers stored or loaded by FX55/FX65, depending on the memory quirk
+    fn increment_i(&mut self, x: u16) {
+        match self.quirks.memory {
+            MemoryQuirk::IncrementByXPlusOne => self.i_register = self.i_register.wrapping_add(x + 1),
+            MemoryQuirk::IncrementByX => self.i_register = self.i_register.wrapping_add(x),
+            MemoryQuirk::Unchanged => (),
+        }
+    }
+
+    // I can point anywhere, but only the low 12 bits reach memory
+    fn read_memory(&self, address: u16) -> u8 {
+        self.memory[address as usize % self.memory.len()]
+    }
+
+    fn write_memory(&mut self, address: u16, value: u8) {
+        let address = address % self.memory.len() as u16;
+        self.memory[address as usize] = value;
+        self.written[address as usize] = true;
+        if self.write_hooks.contains(&address) {
+            self.hooked_writes.push((address, value));
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A machine running `rom`, with fixed timing and no vblank wait so frames run every instruction
+    fn machine(rom: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.load(rom).unwrap();
+        chip8.quirks.vblank = false;
+        chip8.timing = Timing::Fixed;
+        chip8
+    }
+
+    #[test]
+    fn resuming_runs_past_the_breakpoint() {
+        // V0 = 1, then loop forever
+        let mut chip8 = machine(&[0x60, 0x01, 0x12, 0x02]);
+        chip8.breakpoints.insert(0x200);
+        chip8.run_frame(10);
+        assert!(chip8.is_paused());
+        assert_eq!((chip8.pc, chip8.instructions), (0x200, 0));
+
+        chip8.resume();
+        chip8.run_frame(10);
+        assert!(!chip8.is_paused());
+        assert_eq!(chip8.v_register[0], 1);
+    }
+
+    #[test]
+    fn frame_advance_runs_past_the_breakpoint() {
+        // V0 += 1, then back to the start
+        let mut chip8 = machine(&[0x70, 0x01, 0x12, 0x00]);
+        chip8.breakpoints.insert(0x200);
+        chip8.pause();
+        chip8.step_frame(10);
+        // Stops when it comes back round to the breakpoint
+        assert!(chip8.is_paused());
+        assert_eq!((chip8.pc, chip8.v_register[0]), (0x200, 1));
+        chip8.step_frame(10);
+        assert_eq!(chip8.v_register[0], 2);
+    }
+
+    #[test]
+    fn bad_programs_keep_running() {
+        let roms: [&[u8]; 5] = [
+            // Return with nothing on the stack
+            &[0x00, 0xEE],
+            // Call itself forever
+            &[0x22, 0x00],
+            // Point I at the last byte and load, store, draw and look up keys and digits with VF = 0xFF
+            &[0x6F, 0xFF, 0xAF, 0xFF, 0xFF, 0x55, 0xFF, 0x65, 0xFF, 0x33, 0xD0, 0x1F, 0xFF, 0x1E, 0xFF, 0x29, 0xEF, 0x9E, 0xEF, 0xA1, 0x12, 0x00],
+            // Jump past the end of memory
+            &[0x60, 0xFF, 0xBF, 0xFF],
+            // Skip from the last instruction in memory
+            &[0x60, 0x40, 0x61, 0x00, 0xAF, 0xFC, 0xF1, 0x55, 0x1F, 0xFC],
+        ];
+        for rom in roms {
+            let mut chip8 = machine(rom);
+            for _ in 0..10_000 {
+                chip8.tick();
+                assert!(chip8.pc <= 0xFFF && chip8.stack_ptr <= 16);
+            }
+        }
+    }
+
+    #[test]
+    fn stack_wraps_around() {
+        // Return with nothing on the stack, which lands on the zeroed top entry
+        let mut chip8 = machine(&[0x00, 0xEE]);
+        chip8.tick();
+        assert_eq!((chip8.pc, chip8.stack_ptr), (0, 15));
+
+        // Seventeen calls deep, the oldest return address is overwritten
+        let mut chip8 = machine(&[]);
+        for depth in 0..17 {
+            chip8.pc = depth;
+            chip8.op_2nnn(0x200);
         }
+        assert_eq!((chip8.stack_ptr, chip8.stack[0], chip8.stack[1]), (1, 16, 1));
+        chip8.op_00ee();
+        assert_eq!((chip8.stack_ptr, chip8.pc), (0, 16));
+    }
+
+    #[test]
+    fn snapshots_keep_the_random_numbers() {
+        // V0 = random byte, forever
+        let mut chip8 = machine(&[0xC0, 0xFF, 0x12, 0x00]);
+        chip8.seed(1);
+        let draw = |chip8: &mut Chip8| (0..8).map(|_| {
+            chip8.tick();
+            chip8.tick();
+            chip8.v_register[0]
+        }).collect::<Vec<u8>>();
+        draw(&mut chip8);
+        let snapshot = chip8.snapshot().unwrap();
+        let expected = draw(&mut chip8);
+
+        let mut other = machine(&[0xC0, 0xFF, 0x12, 0x00]);
+        other.restore(&snapshot).unwrap();
+        assert_eq!(draw(&mut other), expected);
+    }
+
+    #[test]
+    fn snapshots_the_machine_cant_run_are_refused() {
+        let mut chip8 = machine(&[0x12, 0x00]);
+        let good = chip8.snapshot().unwrap();
+        let bad = [
+            Snapshot { pc: 0xFFF, ..good.clone() },
+            Snapshot { i_register: 0x1000, ..good.clone() },
+            Snapshot { stack_ptr: 17, ..good.clone() },
+            Snapshot { stack_ptr: 1, stack: [0x1000; 16], ..good.clone() },
+        ];
+        for snapshot in bad {
+            assert!(chip8.restore(&snapshot).is_err());
+        }
+        assert!(chip8.restore(&good).is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn snapshots_can_stop_on_the_last_instruction() {
+        let mut chip8 = machine(&[0x22, 0x04, 0x12, 0x02, 0x1F, 0xFE]);
+        chip8.memory[0xFFE..].copy_from_slice(&[0x00, 0xEE]);
+        chip8.tick();
+        chip8.tick();
+        assert_eq!((chip8.pc, chip8.stack_ptr), (MAX_PC, 1));
+        let snapshot = chip8.snapshot().unwrap();
+
+        let mut other = machine(&[0x12, 0x00]);
+        other.restore(&snapshot).unwrap();
+        assert_eq!(other.pc, MAX_PC);
+        other.tick();
+        assert_eq!((other.pc, other.stack_ptr), (0x202, 0));
+    }
+}
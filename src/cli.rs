@@ -0,0 +1,233 @@
+use clap::{Args, Parser, Subcommand};
+
+use crate::tone::{AudioConfig, Waveform};
+use crate::chip8::Quirks;
+use crate::config::Config;
+use crate::display::Palette;
+use crate::loader::Overrides;
+use crate::timing::Timing;
+
+// Size of a CHIP-8 pixel when neither the command line nor the config file set it
+const DEFAULT_SCALE: u16 = 10;
+
+// Fastest multiplier short of turbo. Every emulated frame runs before the next one is drawn, so
+// anything much faster would stop the window from responding
+pub const MAX_SPEED: f64 = 16.0;
+
+#[derive(Parser)]
+#[command(name = "rust-chip8", version, about = "A CHIP-8 interpreter", args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Running without a subcommand is the same as `run`
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a ROM in a window (the default)
+    Run(RunArgs),
+    /// Print the disassembly of a ROM
+    Disasm {
+        /// ROM to disassemble
+        rom: String,
+        /// Address the ROM is loaded at
+        #[arg(long, value_parser = parse_address, default_value = "0x200")]
+        load_address: u16,
+    },
+    /// Show the size, hash and database entry of a ROM
+    Info {
+        /// ROM to look up
+        rom: String,
+    },
+    /// Run a ROM without a window, as fast as possible
+    Headless(HeadlessArgs),
+    /// Serve a JSON-RPC API for driving the emulator from other programs
+    Serve(ServeArgs),
+}
+
+// Options shared by everything that runs a ROM
+#[derive(Args)]
+pub struct MachineArgs {
+    /// ROM to run, or a directory to pick one from
+    pub rom: Option<String>,
+
+    /// Quirk profile, instead of the one from the ROM database
+    #[arg(long, value_name = "original|modern|chip48|superchip|xochip")]
+    pub quirks: Option<Quirks>,
+    /// Instructions per frame, instead of the one from the ROM database
+    #[arg(long, value_name = "N")]
+    pub tickrate: Option<u32>,
+    /// Charge each instruction its cost on the COSMAC VIP instead of running a fixed number per frame
+    #[arg(long, value_name = "fixed|vip")]
+    pub timing: Option<Timing>,
+    /// Seed for the random number generator, for reproducible runs
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Address the ROM is loaded at and started from
+    #[arg(long, value_parser = parse_address, default_value = "0x200")]
+    pub load_address: u16,
+    /// Emulate a COSMAC VIP running this CHIP-8 interpreter image, instead of the built-in interpreter
+    #[arg(long, value_name = "FILE", requires = "vip_monitor")]
+    pub vip_interpreter: Option<String>,
+    /// COSMAC VIP monitor ROM image, needed with --vip-interpreter
+    #[arg(long, value_name = "FILE", requires = "vip_interpreter")]
+    pub vip_monitor: Option<String>,
+    /// Rhai script with hooks on frames, instructions and memory writes
+    #[arg(long, value_name = "FILE.rhai")]
+    pub script: Option<String>,
+
+    /// Colours, instead of the ones from the ROM database
+    #[arg(long, value_name = "green|white|amber|lcd")]
+    pub palette: Option<Palette>,
+    /// Size of a CHIP-8 pixel on screen and in recordings [default: 10]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..=64))]
+    pub scale: Option<u16>,
+
+    /// Beeper waveform [default: square]
+    #[arg(long, value_name = "square|sine|triangle|sawtooth")]
+    pub waveform: Option<Waveform>,
+    /// Beeper frequency in Hz [default: 440]
+    #[arg(long)]
+    pub frequency: Option<f32>,
+    /// Beeper volume from 0 to 1 [default: 0.25]
+    #[arg(long)]
+    pub volume: Option<f32>,
+
+    /// Render the beeper to a WAV file
+    #[arg(long, value_name = "OUT.wav")]
+    pub record_audio: Option<String>,
+    /// Record the screen to an animated GIF, or an APNG if the name ends in .png
+    #[arg(long, value_name = "OUT.gif|OUT.png")]
+    pub record_video: Option<String>,
+
+    /// Ignore the config file, and don't load or save settings and RPL flags for the ROM
+    #[arg(long)]
+    pub no_config: bool,
+}
+
+impl MachineArgs {
+    pub fn load_config(&self) -> Result<Config, String> {
+        if self.no_config {
+            Ok(Config::default())
+        } else {
+            Config::load()
+        }
+    }
+
+    pub fn overrides(&self) -> Overrides {
+        Overrides {
+            palette: self.palette,
+            quirks: self.quirks,
+            tickrate: self.tickrate,
+            timing: self.timing,
+            speed: None,
+        }
+    }
+
+    pub fn scale(&self, config: &Config) -> usize {
+        self.scale.or(config.scale).unwrap_or(DEFAULT_SCALE).clamp(1, 64) as usize
+    }
+
+    pub fn audio_config(&self, config: &Config) -> AudioConfig {
+        let default = AudioConfig::default();
+        AudioConfig {
+            waveform: self.waveform.or(config.waveform).unwrap_or(default.waveform),
+            frequency: self.frequency.or(config.frequency).unwrap_or(default.frequency),
+            volume: self.volume.or(config.volume).unwrap_or(default.volume).clamp(0.0, 1.0),
+            ..default
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub machine: MachineArgs,
+
+    /// Speed multiplier, e.g. 0.5, 2x or turbo [default: 1]
+    #[arg(long, value_parser = parse_speed)]
+    pub speed: Option<f64>,
+    /// File mapping the CHIP-8 keys to keyboard keys
+    #[arg(long, value_name = "FILE")]
+    pub keymap: Option<String>,
+    /// Run without sound
+    #[arg(long)]
+    pub mute: bool,
+    /// Serve the GDB remote protocol on this localhost port
+    #[arg(long, value_name = "PORT", conflicts_with = "vip_interpreter")]
+    pub gdb: Option<u16>,
+}
+
+#[derive(Args)]
+pub struct HeadlessArgs {
+    #[command(flatten)]
+    pub machine: MachineArgs,
+
+    /// Number of 60Hz frames to run
+    #[arg(long, default_value_t = 600)]
+    pub frames: u64,
+    /// Save the screen to a PNG when done
+    #[arg(long, value_name = "OUT.png")]
+    pub screenshot: Option<String>,
+    /// Print the screen to the terminal when done
+    #[arg(long)]
+    pub print_screen: bool,
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub machine: MachineArgs,
+
+    /// Localhost port to listen on
+    #[arg(long, default_value_t = 7878)]
+    pub port: u16,
+    /// Listen on a Unix socket instead of a port
+    #[arg(long, value_name = "PATH", conflicts_with = "port")]
+    pub socket: Option<String>,
+    /// Reinforcement learning spec for the game, enables the env_* methods
+    #[arg(long, value_name = "FILE.toml")]
+    pub env: Option<String>,
+}
+
+// Parse a speed multiplier like "0.5", "2x" or "turbo". Turbo is infinitely fast
+pub fn parse_speed(value: &str) -> Result<f64, String> {
+    if value.eq_ignore_ascii_case("turbo") {
+        return Ok(f64::INFINITY);
+    }
+    match value.trim_end_matches('x').parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed <= MAX_SPEED => Ok(speed),
+        _ => Err(format!("invalid speed '{}', expected a multiplier up to {} like 0.5, 2x or turbo", value, MAX_SPEED)),
+    }
+}
+
+// Parse an address in hex ("0x200") or decimal ("512")
+fn parse_address(value: &str) -> Result<u16, String> {
+    let address = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    match address {
+        Ok(address) if address < 0x1000 => Ok(address),
+        _ => Err(format!("invalid address '{}', expected 0x000-0xfff", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speeds() {
+        assert_eq!(parse_speed("0.5"), Ok(0.5));
+        assert_eq!(parse_speed("2x"), Ok(2.0));
+        assert_eq!(parse_speed("16"), Ok(MAX_SPEED));
+        assert_eq!(parse_speed("Turbo"), Ok(f64::INFINITY));
+        for bad in ["0", "-1", "17", "1e9", "inf", "NaN", "fast", ""] {
+            assert!(parse_speed(bad).is_err(), "{}", bad);
+        }
+    }
+}
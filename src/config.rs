@@ -0,0 +1,331 @@
+// User configuration, kept in the platform config directory (e.g. ~/.config/rust-chip8 on Linux):
+// - config.toml holds the user's defaults and is only ever read
+// - roms.toml holds per-ROM settings keyed by SHA-1, saved whenever a game is tuned in the emulator
+// Saved games are kept in the platform data directory (e.g. ~/.local/share/rust-chip8 on Linux):
+// - rpl/<sha1>.bin holds the SUPER-CHIP RPL user flags of a ROM, which games use for high scores
+// - cheats/<sha1>.toml holds the cheats made for a ROM in the cheat finder
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cheat::Cheat;
+use crate::tone::Waveform;
+use crate::cli;
+use crate::loader::Overrides;
+use crate::osd::OsdElements;
+
+const CONFIG_FILE: &str = "config.toml";
+const ROMS_FILE: &str = "roms.toml";
+const RPL_DIR: &str = "rpl";
+const CHEATS_DIR: &str = "cheats";
+
+// Contents of config.toml. Everything is optional, and the command line wins over it
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    palette: Option<String>,
+    quirks: Option<String>,
+    tickrate: Option<u32>,
+    timing: Option<String>,
+    speed: Option<Speed>,
+    scale: Option<u16>,
+    keymap: Option<String>,
+    mute: bool,
+    waveform: Option<String>,
+    frequency: Option<f32>,
+    volume: Option<f32>,
+    osd: OsdElements,
+}
+
+// Contents of cheats/<sha1>.toml
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct CheatsFile {
+    #[serde(default, rename = "cheat")]
+    cheats: Vec<Cheat>,
+}
+
+// Settings saved for a single ROM
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RomConfig {
+    // Only there to make the file readable, ROMs are matched on their hash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tickrate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<Speed>,
+}
+
+// Speed multiplier, written as a number or as "turbo"
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Speed {
+    Multiplier(f64),
+    Name(String),
+}
+
+impl Speed {
+    pub fn new(speed: f64) -> Self {
+        if speed.is_infinite() {
+            Speed::Name(String::from("turbo"))
+        } else {
+            Speed::Multiplier(speed)
+        }
+    }
+
+    fn parse(&self) -> Result<f64, String> {
+        match self {
+            Speed::Multiplier(speed) if *speed > 0.0 && *speed <= cli::MAX_SPEED => Ok(*speed),
+            Speed::Multiplier(speed) => Err(format!("invalid speed {}, expected a multiplier up to {}", speed, cli::MAX_SPEED)),
+            Speed::Name(name) => cli::parse_speed(name),
+        }
+    }
+}
+
+impl RomConfig {
+    fn overrides(&self) -> Result<Overrides, String> {
+        Ok(Overrides {
+            palette: self.palette.as_deref().map(str::parse).transpose()?,
+            quirks: self.quirks.as_deref().map(str::parse).transpose()?,
+            tickrate: self.tickrate,
+            timing: self.timing.as_deref().map(str::parse).transpose()?,
+            speed: self.speed.as_ref().map(Speed::parse).transpose()?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    dir: Option<PathBuf>,      // Nothing is saved without a config directory
+    data_dir: Option<PathBuf>, // or a data directory
+    pub defaults: Overrides,
+    pub scale: Option<u16>,
+    pub keymap: Option<String>,
+    pub mute: bool,
+    pub waveform: Option<Waveform>,
+    pub frequency: Option<f32>,
+    pub volume: Option<f32>,
+    pub osd: OsdElements,
+    roms: BTreeMap<String, RomConfig>,
+}
+
+impl Config {
+    // Read the config from the platform config directory. Missing files are the same as empty ones
+    pub fn load() -> Result<Self, String> {
+        let mut config = match dirs::config_dir() {
+            Some(dir) => Self::load_from(dir.join("rust-chip8"))?,
+            None => Config::default(),
+        };
+        config.data_dir = dirs::data_dir().map(|dir| dir.join("rust-chip8"));
+        Ok(config)
+    }
+
+    fn load_from(dir: PathBuf) -> Result<Self, String> {
+        let path = dir.join(CONFIG_FILE);
+        let file: ConfigFile = read_toml(&path)?.unwrap_or_default();
+        let roms = read_toml(&dir.join(ROMS_FILE))?.unwrap_or_default();
+        let invalid = |err: String| format!("invalid config {}: {}", path.display(), err);
+
+        let defaults = RomConfig {
+            title: None,
+            palette: file.palette,
+            quirks: file.quirks,
+            tickrate: file.tickrate,
+            timing: file.timing,
+            speed: file.speed,
+        };
+        Ok(Config {
+            dir: Some(dir),
+            data_dir: None,
+            defaults: defaults.overrides().map_err(invalid)?,
+            scale: file.scale,
+            keymap: file.keymap,
+            mute: file.mute,
+            waveform: file.waveform.as_deref().map(str::parse).transpose().map_err(invalid)?,
+            frequency: file.frequency,
+            volume: file.volume,
+            osd: file.osd,
+            roms,
+        })
+    }
+
+    // Settings saved for a ROM, the user's defaults aren't included.
+    // Broken entries are skipped with a warning, as the file is written by the emulator
+    pub fn overrides(&self, sha1: &str) -> Overrides {
+        match self.roms.get(sha1).map(RomConfig::overrides) {
+            Some(Ok(overrides)) => overrides,
+            Some(Err(err)) => {
+                eprintln!("warning: ignoring saved settings for {}: {}", sha1, err);
+                Overrides::default()
+            }
+            None => Overrides::default(),
+        }
+    }
+
+    // Change the saved settings of a ROM and write them out
+    pub fn update_rom(&mut self, sha1: &str, title: &str, update: impl FnOnce(&mut RomConfig)) -> Result<(), String> {
+        let rom = self.roms.entry(sha1.to_string()).or_default();
+        rom.title = Some(title.to_string());
+        update(rom);
+        self.save_roms()
+    }
+
+    fn save_roms(&self) -> Result<(), String> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let path = dir.join(ROMS_FILE);
+        let contents = toml::to_string(&self.roms).map_err(|err| err.to_string())?;
+        fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, contents))
+            .map_err(|err| format!("unable to save {}: {}", path.display(), err))
+    }
+
+    // RPL user flags saved for a ROM, all clear if there are none
+    pub fn load_rpl_flags(&self, sha1: &str) -> [u8; 16] {
+        let mut flags = [0; 16];
+        if let Some(path) = self.rpl_path(sha1) {
+            match fs::read(&path) {
+                Ok(saved) => {
+                    let count = saved.len().min(flags.len());
+                    flags[..count].copy_from_slice(&saved[..count]);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+                Err(err) => eprintln!("warning: unable to read {}: {}", path.display(), err),
+            }
+        }
+        flags
+    }
+
+    // Write out the RPL user flags of a ROM, if they changed since they were loaded
+    pub fn save_rpl_flags(&self, sha1: &str, flags: &[u8; 16]) -> Result<(), String> {
+        let Some(path) = self.rpl_path(sha1) else {
+            return Ok(());
+        };
+        if self.load_rpl_flags(sha1) == *flags {
+            return Ok(());
+        }
+        path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, flags))
+            .map_err(|err| format!("unable to save {}: {}", path.display(), err))
+    }
+
+    fn rpl_path(&self, sha1: &str) -> Option<PathBuf> {
+        self.data_dir.as_ref().map(|dir| dir.join(RPL_DIR).join(format!("{}.bin", sha1)))
+    }
+
+    // Cheats saved for a ROM. A broken file is skipped with a warning
+    pub fn load_cheats(&self, sha1: &str) -> Vec<Cheat> {
+        let Some(path) = self.cheats_path(sha1) else {
+            return Vec::new();
+        };
+        match read_toml::<CheatsFile>(&path) {
+            Ok(file) => file.map_or_else(Vec::new, |file| file.cheats),
+            Err(err) => {
+                eprintln!("warning: {}", err);
+                Vec::new()
+            }
+        }
+    }
+
+    // Write out the cheats of a ROM, removing the file when there are none left
+    pub fn save_cheats(&self, sha1: &str, cheats: &[Cheat]) -> Result<(), String> {
+        let Some(path) = self.cheats_path(sha1) else {
+            return Ok(());
+        };
+        let result = if cheats.is_empty() {
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        } else {
+            let contents = toml::to_string(&CheatsFile { cheats: cheats.to_vec() }).map_err(|err| err.to_string())?;
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&path, contents))
+        };
+        result.map_err(|err| format!("unable to save {}: {}", path.display(), err))
+    }
+
+    fn cheats_path(&self, sha1: &str) -> Option<PathBuf> {
+        self.data_dir.as_ref().map(|dir| dir.join(CHEATS_DIR).join(format!("{}.toml", sha1)))
+    }
+}
+
+fn read_toml<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents)
+            .map(Some)
+            .map_err(|err| format!("invalid config {}: {}", path.display(), err)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("unable to read {}: {}", path.display(), err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn frozen_bytes_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("rust-chip8-cheats-{}", std::process::id()));
+        let config = Config { data_dir: Some(dir.clone()), ..Config::default() };
+        let cheats = vec![
+            Cheat { address: 0x300, value: 3, enabled: true },
+            Cheat { address: 0x301, value: 7, enabled: false },
+        ];
+        config.save_cheats("abc", &cheats).unwrap();
+        assert_eq!(config.load_cheats("abc"), cheats);
+
+        // The enabled one holds its byte whatever the program stores there
+        let mut chip8 = Chip8::new();
+        chip8.load(&[0x60, 0x00, 0xA3, 0x00, 0xF1, 0x55, 0x12, 0x00]).unwrap();
+        chip8.cheats = config.load_cheats("abc");
+        chip8.run_frame(10);
+        assert_eq!(chip8.memory[0x300..0x302], [3, 0]);
+
+        // Saving none removes the file
+        config.save_cheats("abc", &[]).unwrap();
+        assert!(config.load_cheats("abc").is_empty());
+        assert!(!dir.join(CHEATS_DIR).join("abc.toml").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rpl_flags_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("rust-chip8-rpl-{}", std::process::id()));
+        let config = Config { data_dir: Some(dir.clone()), ..Config::default() };
+        assert_eq!(config.load_rpl_flags("abc"), [0; 16]);
+
+        // Clear flags are left unsaved
+        config.save_rpl_flags("abc", &[0; 16]).unwrap();
+        assert!(!dir.join(RPL_DIR).join("abc.bin").exists());
+
+        // A game stores a high score with FX75
+        let mut chip8 = Chip8::new();
+        chip8.load(&[0x60, 0x05, 0x61, 0x07, 0x62, 0x09, 0xF2, 0x75, 0x12, 0x08]).unwrap();
+        chip8.run_frame(10);
+        config.save_rpl_flags("abc", &chip8.rpl_flags).unwrap();
+
+        // And reads it back with FX85 the next time it is started
+        let mut chip8 = Chip8::new();
+        chip8.load(&[0xF2, 0x85, 0x12, 0x02]).unwrap();
+        chip8.rpl_flags = config.load_rpl_flags("abc");
+        chip8.run_frame(10);
+        assert_eq!(chip8.v_register[..3], [5, 7, 9]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
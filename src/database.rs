@@ -0,0 +1,180 @@
+// ROM metadata lookup by SHA-1, using the format of the community CHIP-8 database.
+// Reference: https://github.com/chip-8/chip-8-database
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+use crate::chip8::{MemoryQuirk, Quirks};
+use crate::display::Palette;
+
+const PROGRAMS: &str = include_str!("../database/programs.json");
+const HASHES: &str = include_str!("../database/sha1-hashes.json");
+const PLATFORMS: &str = include_str!("../database/platforms.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    platforms: Vec<String>,
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkFlags>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    default_tickrate: u32,
+    quirks: QuirkFlags,
+}
+
+// Quirks as stored in the database. Per ROM overrides only list the flags that differ
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct QuirkFlags {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl QuirkFlags {
+    // Apply the flags that are set on top of `base`
+    fn apply(&self, base: Quirks) -> Quirks {
+        let memory = match (self.memory_leave_i_unchanged, self.memory_increment_by_x) {
+            (Some(true), _) => MemoryQuirk::Unchanged,
+            (_, Some(true)) => MemoryQuirk::IncrementByX,
+            (Some(false), _) | (_, Some(false)) => MemoryQuirk::IncrementByXPlusOne,
+            (None, None) => base.memory,
+        };
+        Quirks {
+            shift: self.shift.unwrap_or(base.shift),
+            memory,
+            wrap: self.wrap.unwrap_or(base.wrap),
+            jump: self.jump.unwrap_or(base.jump),
+            vblank: self.vblank.unwrap_or(base.vblank),
+            logic: self.logic.unwrap_or(base.logic),
+        }
+    }
+}
+
+// Everything the database knows about a ROM that the emulator can use
+#[derive(Clone, Debug)]
+pub struct RomInfo {
+    pub title: String,
+    pub platform: String,
+    pub quirks: Quirks,
+    pub tickrate: u32,
+    pub palette: Option<Palette>,
+    pub keys: Vec<(String, u8)>, // Name of the action and the CHIP-8 key it is on, e.g. ("up", 0x5)
+}
+
+// The embedded database, parsed the first time a ROM is looked up
+struct Database {
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+    platforms: Vec<Platform>,
+}
+
+fn database() -> &'static Database {
+    static DATABASE: OnceLock<Database> = OnceLock::new();
+    DATABASE.get_or_init(|| Database {
+        hashes: serde_json::from_str(HASHES).expect("Invalid ROM database"),
+        programs: serde_json::from_str(PROGRAMS).expect("Invalid ROM database"),
+        platforms: serde_json::from_str(PLATFORMS).expect("Invalid ROM database"),
+    })
+}
+
+// Find the ROM in the embedded database
+pub fn lookup(rom: &[u8]) -> Option<RomInfo> {
+    let hash = sha1_hex(rom);
+    let Database { hashes, programs, platforms } = database();
+    let program = programs.get(*hashes.get(&hash)?)?;
+    let rom = program.roms.get(&hash)?;
+
+    // The first listed platform is the one the ROM was written for
+    let platform = rom
+        .platforms
+        .iter()
+        .find_map(|id| platforms.iter().find(|platform| &platform.id == id))?;
+    let mut quirks = platform.quirks.apply(Quirks::default());
+    if let Some(overrides) = rom.quirky_platforms.get(&platform.id) {
+        quirks = overrides.apply(quirks);
+    }
+
+    let palette = rom.colors.as_ref().and_then(|colors| {
+        let off = parse_color(colors.pixels.first()?)?;
+        let on = parse_color(colors.pixels.get(1)?)?;
+        Some(Palette { on, off })
+    });
+
+    let mut keys: Vec<(String, u8)> = rom.keys.iter().map(|(name, key)| (name.clone(), *key)).collect();
+    keys.sort_by_key(|(_, key)| *key);
+
+    Some(RomInfo {
+        title: program.title.clone(),
+        platform: platform.id.clone(),
+        quirks,
+        tickrate: rom.tickrate.unwrap_or(platform.default_tickrate),
+        palette,
+        keys,
+    })
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Parse a "#rrggbb" colour into RGBA
+fn parse_color(color: &str) -> Option<[u8; 4]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8, 0xFF])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Holds for the files update.sh fetches as well as the ones in the tree
+    #[test]
+    fn every_hash_leads_to_a_rom_on_a_known_platform() {
+        let Database { hashes, programs, platforms } = database();
+        assert!(!hashes.is_empty());
+        for (hash, &index) in hashes {
+            let program = programs.get(index).unwrap_or_else(|| panic!("{} points past the programs", hash));
+            let rom = program.roms.get(hash).unwrap_or_else(|| panic!("{} isn't a ROM of {}", hash, program.title));
+            assert!(rom.platforms.iter().any(|id| platforms.iter().any(|platform| &platform.id == id)), "{} has no known platform", hash);
+        }
+    }
+
+    #[test]
+    fn ibm_logo() {
+        let info = lookup(include_bytes!("../roms/IBM Logo.ch8")).unwrap();
+        assert_eq!((info.title.as_str(), info.platform.as_str(), info.tickrate), ("IBM Logo", "originalChip8", 15));
+    }
+}
@@ -0,0 +1,70 @@
+// Disassembler using the mnemonics from Cowgod's Chip-8 technical reference.
+// Reference: http://devernay.free.fr/hacks/chip8/C8TECH10.HTM
+
+// Mnemonic for a single opcode. Unknown opcodes are shown as data
+pub fn disassemble(opcode: u16) -> String {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let nn = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+    match ((opcode & 0xF000) >> 12, x, y, n) {
+        (0, 0, 0xE, 0) => "CLS".to_string(),
+        (0, 0, 0xE, 0xE) => "RET".to_string(),
+        (0, _, _, _) => format!("SYS {:#05x}", nnn),
+        (1, _, _, _) => format!("JP {:#05x}", nnn),
+        (2, _, _, _) => format!("CALL {:#05x}", nnn),
+        (3, _, _, _) => format!("SE V{:X}, {:#04x}", x, nn),
+        (4, _, _, _) => format!("SNE V{:X}, {:#04x}", x, nn),
+        (5, _, _, 0) => format!("SE V{:X}, V{:X}", x, y),
+        (6, _, _, _) => format!("LD V{:X}, {:#04x}", x, nn),
+        (7, _, _, _) => format!("ADD V{:X}, {:#04x}", x, nn),
+        (8, _, _, 0) => format!("LD V{:X}, V{:X}", x, y),
+        (8, _, _, 1) => format!("OR V{:X}, V{:X}", x, y),
+        (8, _, _, 2) => format!("AND V{:X}, V{:X}", x, y),
+        (8, _, _, 3) => format!("XOR V{:X}, V{:X}", x, y),
+        (8, _, _, 4) => format!("ADD V{:X}, V{:X}", x, y),
+        (8, _, _, 5) => format!("SUB V{:X}, V{:X}", x, y),
+        (8, _, _, 6) => format!("SHR V{:X}, V{:X}", x, y),
+        (8, _, _, 7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (8, _, _, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (9, _, _, 0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, _, _, _) => format!("LD I, {:#05x}", nnn),
+        (0xB, _, _, _) => format!("JP V0, {:#05x}", nnn),
+        (0xC, _, _, _) => format!("RND V{:X}, {:#04x}", x, nn),
+        (0xD, _, _, _) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0xE, _, 9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, _, 0xA, 1) => format!("SKNP V{:X}", x),
+        (0xF, _, 0, 7) => format!("LD V{:X}, DT", x),
+        (0xF, _, 0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, _, 1, 5) => format!("LD DT, V{:X}", x),
+        (0xF, _, 1, 8) => format!("LD ST, V{:X}", x),
+        (0xF, _, 1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, _, 2, 9) => format!("LD F, V{:X}", x),
+        (0xF, _, 3, 3) => format!("LD B, V{:X}", x),
+        (0xF, _, 5, 5) => format!("LD [I], V{:X}", x),
+        (0xF, _, 6, 5) => format!("LD V{:X}, [I]", x),
+        (0xF, _, 7, 5) => format!("LD R, V{:X}", x),
+        (0xF, _, 8, 5) => format!("LD V{:X}, R", x),
+        (_, _, _, _) => format!("DW {:#06x}", opcode),
+    }
+}
+
+// Listing of a whole ROM, one opcode per line, e.g. `0x200: 00e0  CLS`.
+// Data mixed in with the code is disassembled too, since it can't be told apart without running the program
+pub fn listing(rom: &[u8], load_address: u16) -> Vec<String> {
+    rom.chunks(2)
+        .enumerate()
+        .map(|(i, bytes)| {
+            let address = load_address as usize + i * 2;
+            match bytes {
+                [high, low] => {
+                    let opcode = (*high as u16) << 8 | *low as u16;
+                    format!("{:#05x}: {:04x}  {}", address, opcode, disassemble(opcode))
+                }
+                [byte] => format!("{:#05x}: {:02x}    DB {:#04x}", address, byte, byte),
+                _ => unreachable!(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,99 @@
+use std::str::FromStr;
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+// Colours used for lit and unlit pixels, in RGBA
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub on: [u8; 4],
+    pub off: [u8; 4],
+}
+
+impl Palette {
+    pub const GREEN: Palette = Palette { on: [0x00, 0xFF, 0x00, 0xFF], off: [0x00, 0x00, 0x00, 0xFF] };
+    pub const WHITE: Palette = Palette { on: [0xFF, 0xFF, 0xFF, 0xFF], off: [0x00, 0x00, 0x00, 0xFF] };
+    pub const AMBER: Palette = Palette { on: [0xFF, 0xB0, 0x00, 0xFF], off: [0x1A, 0x10, 0x00, 0xFF] };
+    pub const LCD: Palette = Palette { on: [0x0F, 0x38, 0x0F, 0xFF], off: [0x9B, 0xBC, 0x0F, 0xFF] };
+
+    pub fn color(&self, pixel: bool) -> [u8; 4] {
+        if pixel { self.on } else { self.off }
+    }
+}
+
+// Palettes that can be picked by name, in the order F2 cycles through them
+pub const PALETTES: [(&str, Palette); 4] = [
+    ("green", Palette::GREEN),
+    ("white", Palette::WHITE),
+    ("amber", Palette::AMBER),
+    ("lcd", Palette::LCD),
+];
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::GREEN
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PALETTES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, palette)| *palette)
+            .ok_or_else(|| format!("unknown palette '{}', expected green, white, amber or lcd", s))
+    }
+}
+
+// Convert the screen to RGBA, writing into a frame of 4 bytes per pixel
+pub fn draw(screen: &[bool], palette: &Palette, frame: &mut [u8]) {
+    for (pixel, rgba) in screen.iter().zip(frame.chunks_exact_mut(4)) {
+        rgba.copy_from_slice(&palette.color(*pixel));
+    }
+}
+
+// Convert the screen to an RGBA image with every pixel blown up to a scale x scale block
+pub fn to_rgba(screen: &[bool], palette: &Palette, scale: usize) -> Vec<u8> {
+    let scaled = scale_up(screen, scale);
+    let mut image = vec![0; scaled.len() * 4];
+    draw(&scaled, palette, &mut image);
+    image
+}
+
+// Same as `to_rgba` but with one byte per pixel: 0 for unlit, 1 for lit
+pub fn to_indexed(screen: &[bool], scale: usize) -> Vec<u8> {
+    scale_up(screen, scale).into_iter().map(u8::from).collect()
+}
+
+// One byte per 8 pixels, leftmost pixel in the top bit, rows in order. The layout of the VIP's display buffer
+pub fn to_bits(screen: &[bool]) -> Vec<u8> {
+    screen
+        .chunks(8)
+        .map(|pixels| pixels.iter().fold(0, |byte, pixel| byte << 1 | u8::from(*pixel)))
+        .collect()
+}
+
+// The screen as text, a line per row with `#` for lit and `.` for unlit pixels
+pub fn to_text(screen: &[bool]) -> Vec<String> {
+    screen
+        .chunks(WIDTH)
+        .map(|row| row.iter().map(|pixel| if *pixel { '#' } else { '.' }).collect())
+        .collect()
+}
+
+// Blow every pixel up to a scale x scale block
+fn scale_up(screen: &[bool], scale: usize) -> Vec<bool> {
+    let mut image = Vec::with_capacity(WIDTH * HEIGHT * scale * scale);
+    for row in screen.chunks_exact(WIDTH) {
+        let line: Vec<bool> = row
+            .iter()
+            .flat_map(|pixel| std::iter::repeat_n(*pixel, scale))
+            .collect();
+        for _ in 0..scale {
+            image.extend_from_slice(&line);
+        }
+    }
+    image
+}
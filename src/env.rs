@@ -0,0 +1,220 @@
+// Reinforcement learning environment in the style of OpenAI Gym: reset, step with an action, get back the
+// screen, a reward and whether the episode is over. Rewards and the end of an episode are read from the
+// game's memory at addresses given in a per-game spec file:
+//
+//     frame_skip = 4                                # frames run by each step
+//     actions = [[], [4], [6]]                      # keys held down for each action
+//     score = { address = 0x2F0, format = "bcd" }   # reward is how much this went up
+//     done = { address = 0x2F3, equals = 0 }        # the episode ends when this byte has this value
+//     max_steps = 10000                             # or after this many steps
+//
+// Steps run back to back at full speed, like `headless`. The spec is read by whoever embeds the environment:
+// `serve --env` loads it from TOML and the Python bindings take it as JSON.
+
+use serde::Deserialize;
+
+use crate::chip8::Chip8;
+use crate::display;
+
+const DEFAULT_FRAME_SKIP: u32 = 4;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvSpec {
+    #[serde(default = "default_frame_skip")]
+    pub frame_skip: u32,
+    // Without a list, action 0 holds no key and action N holds key N - 1
+    pub actions: Option<Vec<Vec<u8>>>,
+    pub score: Option<Score>,
+    pub done: Option<Done>,
+    pub max_steps: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Score {
+    pub address: u16,
+    #[serde(default)]
+    pub format: ScoreFormat,
+}
+
+// How a game stores its score
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreFormat {
+    #[default]
+    Byte,
+    Word, // Two bytes, high byte first
+    Bcd,  // Three bytes of decimal digits, as stored by FX33
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Done {
+    pub address: u16,
+    pub equals: u8,
+}
+
+fn default_frame_skip() -> u32 {
+    DEFAULT_FRAME_SKIP
+}
+
+// Every key on its own as an action, with no reward and no end
+impl Default for EnvSpec {
+    fn default() -> Self {
+        EnvSpec { frame_skip: DEFAULT_FRAME_SKIP, actions: None, score: None, done: None, max_steps: None }
+    }
+}
+
+impl EnvSpec {
+    // Refuse specs that would make every step fail, to be called after deserializing one
+    pub fn check(&self) -> Result<(), String> {
+        if self.frame_skip == 0 {
+            return Err(String::from("frame_skip must be at least 1"));
+        }
+        if let Some(actions) = &self.actions {
+            if actions.is_empty() || actions.iter().flatten().any(|&key| key > 0xF) {
+                return Err(String::from("actions must be a non-empty list of lists of keys 0-15"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.actions.as_ref().map_or(17, Vec::len)
+    }
+
+    fn keys(&self, action: usize) -> Vec<u8> {
+        match &self.actions {
+            Some(actions) => actions[action].clone(),
+            None if action == 0 => Vec::new(),
+            None => vec![action as u8 - 1],
+        }
+    }
+}
+
+// What one step returns
+pub struct Step {
+    pub observation: Vec<u8>,
+    pub reward: f64,
+    pub done: bool,
+}
+
+// Owns the machine it drives, which has to have the game loaded before the first reset
+pub struct Env {
+    pub chip8: Chip8,
+    spec: EnvSpec,
+    tickrate: u32,
+    score: i64,
+    steps: u64,
+    done: bool,
+}
+
+impl Env {
+    pub fn new(chip8: Chip8, spec: EnvSpec, tickrate: u32) -> Self {
+        Env { chip8, spec, tickrate, score: 0, steps: 0, done: false }
+    }
+
+    pub fn spec(&self) -> &EnvSpec {
+        &self.spec
+    }
+
+    // Change the instructions per frame, e.g. after a different game is loaded
+    pub fn set_tickrate(&mut self, tickrate: u32) {
+        self.tickrate = tickrate;
+    }
+
+    // Restart the game, seeding the random number generator first if a seed is given.
+    // Returns the first observation
+    pub fn reset(&mut self, seed: Option<u64>) -> Vec<u8> {
+        if let Some(seed) = seed {
+            self.chip8.seed(seed);
+        }
+        self.chip8.reset();
+        self.chip8.resume();
+        self.score = self.read_score();
+        self.steps = 0;
+        self.done = false;
+        self.observation()
+    }
+
+    // Hold the action's keys down for `frame_skip` frames. The reward is how much the score went up
+    pub fn step(&mut self, action: usize) -> Result<Step, String> {
+        if action >= self.spec.action_count() {
+            return Err(format!("action must be less than {}", self.spec.action_count()));
+        }
+        if self.done {
+            return Err(String::from("the episode is over, reset the environment"));
+        }
+        let keys = self.spec.keys(action);
+        for (key, pressed) in self.chip8.key.iter_mut().enumerate() {
+            *pressed = keys.contains(&(key as u8));
+        }
+        for _ in 0..self.spec.frame_skip {
+            self.chip8.step_frame(self.tickrate);
+        }
+        self.steps += 1;
+
+        let score = self.read_score();
+        let reward = (score - self.score) as f64;
+        self.score = score;
+        let memory = &self.chip8.memory;
+        let game_over = self.spec.done.is_some_and(|done| memory[done.address as usize % memory.len()] == done.equals);
+        self.done = game_over || self.spec.max_steps.is_some_and(|max| self.steps >= max);
+        Ok(Step { observation: self.observation(), reward, done: self.done })
+    }
+
+    // The screen as text, for watching an agent play
+    pub fn render(&self) -> Vec<String> {
+        display::to_text(&self.chip8.screen)
+    }
+
+    // The 64x32 screen packed 8 pixels to a byte, see `display::to_bits`
+    fn observation(&self) -> Vec<u8> {
+        display::to_bits(&self.chip8.screen)
+    }
+
+    fn read_score(&self) -> i64 {
+        let Some(score) = self.spec.score else { return 0 };
+        let memory = &self.chip8.memory;
+        let byte = |offset: u16| memory[score.address.wrapping_add(offset) as usize % memory.len()] as i64;
+        match score.format {
+            ScoreFormat::Byte => byte(0),
+            ScoreFormat::Word => byte(0) << 8 | byte(1),
+            ScoreFormat::Bcd => byte(0) * 100 + byte(1) * 10 + byte(2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::Timing;
+
+    #[test]
+    fn rewards_and_episode_end() {
+        // Count up in 0x300, one loop of four instructions per frame
+        let mut chip8 = Chip8::new();
+        chip8.load(&[0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00]).unwrap();
+        chip8.timing = Timing::Fixed;
+        let spec = EnvSpec {
+            frame_skip: 1,
+            score: Some(Score { address: 0x300, format: ScoreFormat::Byte }),
+            done: Some(Done { address: 0x300, equals: 3 }),
+            ..EnvSpec::default()
+        };
+        let mut env = Env::new(chip8, spec, 4);
+
+        assert_eq!(env.reset(Some(1)), vec![0; 256]);
+        for done in [false, false, true] {
+            let step = env.step(0).unwrap();
+            assert_eq!((step.reward, step.done), (1.0, done));
+        }
+        assert!(env.step(0).is_err());
+        assert!(env.step(17).is_err());
+
+        env.reset(None);
+        assert_eq!(env.chip8.memory[0x300], 0);
+        assert!(env.step(1).is_ok());
+    }
+}
@@ -0,0 +1,652 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use pixels::{Pixels, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::window::WindowBuilder;
+
+use crate::audio::Audio;
+use crate::cheatview::CheatFinder;
+use crate::chip8::{Chip8, DEFAULT_TICKRATE, QUIRK_PROFILES};
+use crate::cli::RunArgs;
+use crate::config::{Config, RomConfig, Speed};
+use crate::display::{self, HEIGHT, PALETTES, WIDTH};
+use crate::gdb::GdbStub;
+use crate::keymap::Keymap;
+use crate::loader::{self, Overrides, RomSettings};
+use crate::memview::MemoryViewer;
+use crate::menu::{MenuAction, RomBrowser};
+use crate::osd::Osd;
+use crate::recorder::FrameRecorder;
+use crate::screenshot::{self, ScreenshotFormat};
+use crate::text::Canvas;
+use crate::wav::{WavRecorder, WavRecording};
+
+// ROM started when none is given on the command line
+const DEFAULT_ROM: &str = "roms/IBM Logo.ch8";
+
+// The frame buffer is larger than the screen so text can be drawn over it
+const BUFFER_SCALE: usize = 8;
+
+// Speeds the -/= hotkeys step through. Infinity runs as fast as possible
+const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, f64::INFINITY];
+
+// Time per 60Hz frame spent emulating in turbo mode, the rest is left for drawing
+const TURBO_BUDGET: Duration = Duration::from_millis(12);
+
+// Run a ROM in a window until it is closed
+pub fn run(args: RunArgs) -> Result<(), String> {
+    let RunArgs { machine, speed, keymap, mute, gdb } = args;
+    let mut config = machine.load_config()?;
+    let keymap = match keymap.as_ref().or(config.keymap.as_ref()) {
+        Some(path) => Keymap::load(path)?,
+        None => Keymap::default(),
+    };
+    let overrides = Overrides { speed, ..machine.overrides() };
+    let audio_config = machine.audio_config(&config);
+    let scale = machine.scale(&config);
+
+    let audio_stream = if mute || config.mute { Audio::null() } else { Audio::new(audio_config) };
+    let mut chip8 = loader::new_machine(&machine)?;
+    let mut osd = Osd::new(config.osd);
+
+    // A directory opens the ROM browser instead of running a ROM
+    let rom_path = machine.rom.clone().unwrap_or_else(|| DEFAULT_ROM.to_string());
+    let mut menu = None;
+    let mut current_rom = None;
+    let defaults = overrides.or(config.defaults);
+    let mut settings = RomSettings {
+        tickrate: defaults.tickrate.unwrap_or(DEFAULT_TICKRATE),
+        palette: defaults.palette.unwrap_or_default(),
+        speed: defaults.speed.unwrap_or(1.0),
+        title: String::from("Chip-8"),
+        sha1: String::new(),
+        info: None,
+    };
+    if Path::new(&rom_path).is_dir() {
+        menu = Some(RomBrowser::new(Path::new(&rom_path)));
+    } else {
+        settings = loader::open_rom(&mut chip8, Path::new(&rom_path), &overrides, &config)?;
+        if let Some(info) = &settings.info {
+            loader::show_rom_info(info, &keymap, &mut osd);
+        }
+        current_rom = Some(PathBuf::from(&rom_path));
+    }
+    let mut speed = settings.speed;
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(window_title(&settings.title, false, speed))
+        .with_inner_size(LogicalSize::new((WIDTH * scale) as f64, (HEIGHT * scale) as f64))
+        .build(&event_loop)
+        .map_err(|err| format!("unable to create window: {}", err))?;
+    let mut pixels = {
+        let window_size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
+        Pixels::new((WIDTH * BUFFER_SCALE) as u32, (HEIGHT * BUFFER_SCALE) as u32, surface_texture)
+            .map_err(|err| format!("unable to create frame buffer: {}", err))?
+    };
+
+    // Render the beeper from the sound timer on each emulated 60Hz tick
+    let wav_recording = match &machine.record_audio {
+        Some(path) => {
+            let recorder = WavRecorder::new(path, audio_config)
+                .map_err(|err| format!("unable to create {}: {}", path, err))?;
+            Some(WavRecording::start(&mut chip8.timer, recorder))
+        }
+        None => None,
+    };
+
+    // Presented frames are stamped with the emulated frame number, so recordings keep the game's timing
+    // whatever the speed, and leave out time spent paused
+    let mut frame_recorder = machine
+        .record_video
+        .as_ref()
+        .map(|path| FrameRecorder::new(path, settings.palette, scale));
+
+    let mut viewer: Option<MemoryViewer> = None;
+    let mut cheat_finder = CheatFinder::new();
+    let mut show_cheats = false;
+    let mut modifiers = ModifiersState::empty();
+    let mut gdb = gdb.map(GdbStub::new).transpose()?;
+
+    let timer_length = Duration::new(0, 16666666); // This is 60Hz
+    let mut next_frame = Instant::now();
+    // Emulated frames owed when running slower or faster than 60Hz
+    let mut frame_credit = 0.0;
+    event_loop.run(move |event, _, control_flow| {
+        if chip8.timer.get_st() != 0 && menu.is_none() && !chip8.is_paused() {
+            audio_stream.play();
+        } else {
+            audio_stream.pause();
+        }
+
+        match event {
+            // F9 starts and stops recording the screen
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F9),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => match frame_recorder.take() {
+                Some(recorder) => match finish_recording(recorder, chip8.frames) {
+                    Ok(message) => osd.message(message),
+                    Err(err) => osd.error(err),
+                },
+                None => {
+                    let path = screenshot::timestamped_path("recording", "gif");
+                    osd.message(format!("Recording to {}", path));
+                    let mut recorder = FrameRecorder::new(&path, settings.palette, scale);
+                    recorder.capture(chip8.frames, &chip8.screen);
+                    frame_recorder = Some(recorder);
+                }
+            }
+            // F12 saves a screenshot, Shift+F12 saves it as a raw 1-bit image
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F12),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                let format = if modifiers.shift() {
+                    ScreenshotFormat::Raw
+                } else {
+                    ScreenshotFormat::Color { palette: settings.palette, scale }
+                };
+                match screenshot::save_screenshot(&chip8.screen, format) {
+                    Ok(path) => osd.message(format!("Saved screenshot to {}", path)),
+                    Err(err) => osd.error(format!("Unable to save screenshot: {}", err)),
+                }
+            }
+            Event::WindowEvent { event: WindowEvent::ModifiersChanged(state), .. } => {
+                modifiers = state;
+            }
+            // While the ROM browser is open it gets all key presses
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } if menu.is_some() => {
+                match menu.as_mut().unwrap().key_pressed(key) {
+                    MenuAction::Load(path) => {
+                        if let Err(err) = save_rpl_flags(&config, &settings, &chip8) {
+                            osd.error(err);
+                        }
+                        match loader::open_rom(&mut chip8, &path, &overrides, &config) {
+                            Ok(loaded) => {
+                                if let Some(info) = &loaded.info {
+                                    loader::show_rom_info(info, &keymap, &mut osd);
+                                }
+                                settings = loaded;
+                                speed = settings.speed;
+                                cheat_finder = CheatFinder::new();
+                                window.set_title(&window_title(&settings.title, chip8.is_paused(), speed));
+                                current_rom = Some(path);
+                                menu = None;
+                                osd.clear_error();
+                            }
+                            Err(err) => osd.error(err),
+                        }
+                    }
+                    // Only go back if there is a game to go back to
+                    MenuAction::Close => {
+                        if current_rom.is_some() {
+                            menu = None;
+                        }
+                    }
+                    MenuAction::None => (),
+                }
+                window.request_redraw();
+            }
+            // F6, F7, F8 and F10 show and hide the frame rate, instruction rate, status and messages
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key @ (VirtualKeyCode::F6 | VirtualKeyCode::F7 | VirtualKeyCode::F8 | VirtualKeyCode::F10)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                let element = match key {
+                    VirtualKeyCode::F6 => &mut osd.elements.fps,
+                    VirtualKeyCode::F7 => &mut osd.elements.ips,
+                    VirtualKeyCode::F8 => &mut osd.elements.status,
+                    _ => &mut osd.elements.messages,
+                };
+                *element = !*element;
+                window.request_redraw();
+            }
+            // F4 shows and hides the memory viewer
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F4),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                viewer = match viewer {
+                    Some(_) => None,
+                    None => Some(MemoryViewer::new(&mut chip8)),
+                };
+                show_cheats = false;
+                chip8.key = [false; 16];
+                window.request_redraw();
+            }
+            // F11 shows and hides the cheat finder
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F11),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                show_cheats = !show_cheats;
+                viewer = None;
+                chip8.key = [false; 16];
+                window.request_redraw();
+            }
+            // While the memory viewer is open it gets the keys it uses for moving and editing
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } if viewer.is_some() && MemoryViewer::handles(key) => {
+                viewer.as_mut().unwrap().key_pressed(key, &mut chip8);
+                window.request_redraw();
+            }
+            // Likewise for the cheat finder, which saves the cheats for the ROM whenever they change
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } if show_cheats && menu.is_none() && CheatFinder::handles(key) => {
+                if cheat_finder.key_pressed(key, &mut chip8) && !settings.sha1.is_empty() {
+                    if let Err(err) = config.save_cheats(&settings.sha1, &chip8.cheats) {
+                        osd.error(format!("Unable to save cheats: {}", err));
+                    }
+                }
+                window.request_redraw();
+            }
+            // Escape opens the ROM browser in the directory of the current ROM
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                let dir = current_rom
+                    .as_deref()
+                    .and_then(Path::parent)
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."));
+                let mut browser = RomBrowser::new(dir);
+                if let Some(path) = &current_rom {
+                    browser.select(path);
+                }
+                menu = Some(browser);
+                chip8.key = [false; 16];
+                window.request_redraw();
+            }
+            // P pauses and resumes, N advances a single frame and F5 resets the game
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key @ (VirtualKeyCode::P | VirtualKeyCode::N | VirtualKeyCode::F5)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                match key {
+                    VirtualKeyCode::P if chip8.is_paused() => chip8.resume(),
+                    VirtualKeyCode::P => chip8.pause(),
+                    VirtualKeyCode::N => {
+                        chip8.pause();
+                        chip8.step_frame(settings.tickrate);
+                    }
+                    _ => chip8.reset(),
+                }
+                window.set_title(&window_title(&settings.title, chip8.is_paused(), speed));
+                window.request_redraw();
+            }
+            // Tuning the game: -/= change the speed, [/] the instructions per frame, F2 the palette and
+            // F3 the quirk profile. Changes are saved for the ROM
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        virtual_keycode:
+                        Some(key @ (VirtualKeyCode::Minus | VirtualKeyCode::Equals | VirtualKeyCode::LBracket | VirtualKeyCode::RBracket | VirtualKeyCode::F2 | VirtualKeyCode::F3)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                let update: Box<dyn FnOnce(&mut RomConfig)> = match key {
+                    VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                        speed = if key == VirtualKeyCode::Minus {
+                            SPEEDS.iter().rev().copied().find(|s| *s < speed).unwrap_or(SPEEDS[0])
+                        } else {
+                            SPEEDS.iter().copied().find(|s| *s > speed).unwrap_or(speed)
+                        };
+                        Box::new(move |rom| rom.speed = Some(Speed::new(speed)))
+                    }
+                    VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                        settings.tickrate = if key == VirtualKeyCode::LBracket {
+                            settings.tickrate.saturating_sub(1).max(1)
+                        } else {
+                            settings.tickrate + 1
+                        };
+                        osd.message(format!("Instructions per frame: {}", settings.tickrate));
+                        let tickrate = settings.tickrate;
+                        Box::new(move |rom| rom.tickrate = Some(tickrate))
+                    }
+                    VirtualKeyCode::F2 => {
+                        let (name, palette) = next_preset(&PALETTES, &settings.palette);
+                        settings.palette = palette;
+                        osd.message(format!("Palette: {}", name));
+                        Box::new(move |rom| rom.palette = Some(name.to_string()))
+                    }
+                    _ => {
+                        let (name, quirks) = next_preset(&QUIRK_PROFILES, &chip8.quirks);
+                        chip8.quirks = quirks;
+                        osd.message(format!("Quirks: {}", name));
+                        Box::new(move |rom| rom.quirks = Some(name.to_string()))
+                    }
+                };
+                if let (Some(path), None) = (&current_rom, &menu) {
+                    // Name the entry after the game, or the file if the game isn't known
+                    let title = match &settings.info {
+                        Some(info) => info.title.clone(),
+                        None => path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                    };
+                    if let Err(err) = config.update_rom(&settings.sha1, &title, update) {
+                        osd.error(format!("Unable to save settings: {}", err));
+                    }
+                }
+                window.set_title(&window_title(&settings.title, chip8.is_paused(), speed));
+                window.request_redraw();
+            }
+            // CHIP-8 keypad, see keymap.rs for the layout. The memory viewer and cheat finder use hex digits and
+            // letters that are also keypad keys, so the game gets no input while either is open
+            Event::WindowEvent {
+                event:
+                WindowEvent::KeyboardInput {
+                    input:
+                    KeyboardInput {
+                        scancode,
+                        state,
+                        ..
+                    },
+                    ..
+                },
+                ..
+            } => {
+                let overlay_open = viewer.is_some() || show_cheats;
+                if let Some(key) = keymap.chip8_key(scancode).filter(|_| !overlay_open) {
+                    chip8.key[key] = state == ElementState::Pressed;
+                }
+            }
+            // Dropping a ROM on the window starts it
+            Event::WindowEvent { event: WindowEvent::DroppedFile(path), .. } => {
+                if let Err(err) = save_rpl_flags(&config, &settings, &chip8) {
+                    osd.error(err);
+                }
+                match loader::open_rom(&mut chip8, &path, &overrides, &config) {
+                    Ok(loaded) => {
+                        if let Some(info) = &loaded.info {
+                            loader::show_rom_info(info, &keymap, &mut osd);
+                        }
+                        settings = loaded;
+                        speed = settings.speed;
+                        cheat_finder = CheatFinder::new();
+                        window.set_title(&window_title(&settings.title, chip8.is_paused(), speed));
+                        current_rom = Some(path);
+                        menu = None;
+                        osd.clear_error();
+                    }
+                    Err(err) => osd.error(err),
+                }
+                window.request_redraw();
+            }
+            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
+                let status = shut_down(&config, &settings, &chip8, wav_recording.as_ref(), frame_recorder.take());
+                control_flow.set_exit_with_code(status);
+            }
+            Event::MainEventsCleared => {
+                // Run one frame worth of instructions every 60th of a second
+                let now = Instant::now();
+                if now >= next_frame {
+                    // The debugger is served between frames, so it sees the machine at a frame boundary or
+                    // wherever it stopped
+                    if let Some(gdb) = gdb.as_mut() {
+                        if gdb.poll(&mut chip8, &mut osd) {
+                            window.request_redraw();
+                        }
+                    }
+                    if menu.is_none() {
+                        // Run emulated frames at speed x 60Hz, so instructions and timers scale together
+                        let mut drawn = false;
+                        if speed.is_infinite() {
+                            while now.elapsed() < TURBO_BUDGET && !chip8.is_paused() {
+                                chip8.run_frame(settings.tickrate);
+                                drawn |= chip8.draw_flag;
+                            }
+                        } else {
+                            frame_credit += speed;
+                            while frame_credit >= 1.0 {
+                                chip8.run_frame(settings.tickrate);
+                                drawn |= chip8.draw_flag;
+                                frame_credit -= 1.0;
+                            }
+                        }
+                        if drawn {
+                            window.request_redraw();
+                        }
+                        // Memory changes without anything being drawn, so keep the viewer up to date
+                        if let Some(viewer) = viewer.as_mut() {
+                            viewer.update(&mut chip8);
+                            window.request_redraw();
+                        }
+                        if show_cheats {
+                            window.request_redraw();
+                        }
+                    }
+                    // A script asked to exit, which saves everything the same way as closing the window
+                    if let Some(code) = chip8.quit_requested() {
+                        let status = shut_down(&config, &settings, &chip8, wav_recording.as_ref(), frame_recorder.take());
+                        control_flow.set_exit_with_code(status.max(code));
+                        return;
+                    }
+                    if osd.update(chip8.frames, chip8.instructions) {
+                        window.request_redraw();
+                    }
+                    let hud = chip8.overlay();
+                    if hud != osd.hud {
+                        osd.hud = hud;
+                        window.request_redraw();
+                    }
+                    // Don't try to catch up on frames missed while the window was blocked
+                    next_frame = (next_frame + timer_length).max(now);
+                }
+                control_flow.set_wait_until(next_frame);
+            }
+            Event::RedrawRequested(_) => {
+                let frame = pixels.get_frame_mut();
+                match (menu.as_mut(), viewer.as_mut()) {
+                    (Some(browser), _) => {
+                        let mut canvas = Canvas::new(frame, WIDTH * BUFFER_SCALE, HEIGHT * BUFFER_SCALE);
+                        browser.draw(&mut canvas, &settings.palette);
+                    }
+                    (None, Some(viewer)) => {
+                        let mut canvas = Canvas::new(frame, WIDTH * BUFFER_SCALE, HEIGHT * BUFFER_SCALE);
+                        viewer.draw(&mut canvas, &settings.palette, &chip8);
+                    }
+                    (None, None) if show_cheats => {
+                        let mut canvas = Canvas::new(frame, WIDTH * BUFFER_SCALE, HEIGHT * BUFFER_SCALE);
+                        cheat_finder.draw(&mut canvas, &settings.palette, &chip8);
+                    }
+                    (None, None) => {
+                        frame.copy_from_slice(&display::to_rgba(&chip8.screen, &settings.palette, BUFFER_SCALE));
+                    }
+                }
+                if menu.is_none() {
+                    if let Some(recorder) = frame_recorder.as_mut() {
+                        recorder.capture(chip8.frames, &chip8.screen);
+                    }
+                }
+                let mut canvas = Canvas::new(pixels.get_frame_mut(), WIDTH * BUFFER_SCALE, HEIGHT * BUFFER_SCALE);
+                osd.draw(&mut canvas, chip8.is_paused(), speed);
+                if let Err(err) = pixels.render() {
+                    eprintln!("Unable to draw the screen: {}", err);
+                    let status = shut_down(&config, &settings, &chip8, wav_recording.as_ref(), frame_recorder.take());
+                    control_flow.set_exit_with_code(status.max(1));
+                }
+            }
+            _ => ()
+        }
+    });
+}
+
+// The preset after the current one, or the first if the current setting isn't a preset
+fn next_preset<T: Copy + PartialEq>(presets: &[(&'static str, T)], current: &T) -> (&'static str, T) {
+    let next = presets
+        .iter()
+        .position(|(_, preset)| preset == current)
+        .map_or(0, |i| (i + 1) % presets.len());
+    presets[next]
+}
+
+// Window title with the speed and pause state, e.g. "Chip-8 - IBM Logo [2x] (paused)"
+fn window_title(title: &str, paused: bool, speed: f64) -> String {
+    let mut window_title = title.to_string();
+    if speed.is_infinite() {
+        window_title.push_str(" [turbo]");
+    } else if speed != 1.0 {
+        window_title.push_str(&format!(" [{}x]", speed));
+    }
+    if paused {
+        window_title.push_str(" (paused)");
+    }
+    window_title
+}
+
+// Keep the RPL user flags (high scores) of the running game before it is replaced or the window closes
+fn save_rpl_flags(config: &Config, settings: &RomSettings, chip8: &Chip8) -> Result<(), String> {
+    if settings.sha1.is_empty() {
+        return Ok(());
+    }
+    config
+        .save_rpl_flags(&settings.sha1, &chip8.rpl_flags)
+        .map_err(|err| format!("Unable to save RPL flags: {}", err))
+}
+
+// Returns the message to show once the recording is written
+fn finish_recording(recorder: FrameRecorder, end_frame: u64) -> Result<String, String> {
+    let path = recorder.path().to_string();
+    recorder
+        .finish(end_frame)
+        .map(|()| format!("Saved recording to {}", path))
+        .map_err(|err| format!("Unable to save recording: {}", err))
+}
+
+// Save everything that is written out on the way out. The window is going away, so messages and errors
+// go to the terminal, and an error makes the emulator exit with a failure status
+fn shut_down(
+    config: &Config,
+    settings: &RomSettings,
+    chip8: &Chip8,
+    wav_recording: Option<&WavRecording>,
+    frame_recorder: Option<FrameRecorder>,
+) -> i32 {
+    let mut results = vec![save_rpl_flags(config, settings, chip8).map(|()| None)];
+    if let Some(recording) = wav_recording {
+        results.push(recording.finish().map(|()| None).map_err(|err| format!("Unable to save audio recording: {}", err)));
+    }
+    if let Some(recorder) = frame_recorder {
+        results.push(finish_recording(recorder, chip8.frames).map(Some));
+    }
+    let mut status = 0;
+    for result in results {
+        match result {
+            Ok(Some(message)) => println!("{}", message),
+            Ok(None) => (),
+            Err(err) => {
+                eprintln!("{}", err);
+                status = 1;
+            }
+        }
+    }
+    status
+}
@@ -0,0 +1,567 @@
+// GDB remote serial protocol stub, so gdb or an IDE debug adapter can debug the running ROM.
+// Reference: https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html
+//
+// The registers are PC, I and SP (16 bit, little endian) followed by V0-VF, and the target memory is the
+// 4K of CHIP-8 memory. Breakpoints are checked by the machine before every instruction, stepping runs one
+// instruction with `Chip8::tick`, and continuing lets the frame loop run until a breakpoint or a pause.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::chip8::{Chip8, MAX_PC};
+use crate::osd::Osd;
+
+// Register numbers in the order of the `g` packet and the target description
+const PC: usize = 0;
+const I: usize = 1;
+const SP: usize = 2;
+const V0: usize = 3;
+const REGISTER_COUNT: usize = V0 + 16;
+
+// Highest I gdb can set, it has to point into the 4K of memory. PC goes up to the last whole instruction
+const MAX_I: u16 = 0xFFF;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rust-chip8.core">
+    <reg name="pc" bitsize="16" type="code_ptr" regnum="0"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="sp" bitsize="16" type="uint16"/>
+    <reg name="v0" bitsize="8" type="uint8"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// Stop replies: SIGTRAP after a step or at a breakpoint, SIGINT when interrupted
+const STOP_TRAP: &str = "S05";
+const STOP_INTERRUPT: &str = "S02";
+
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<Client>,
+}
+
+struct Client {
+    stream: TcpStream,
+    input: Vec<u8>,
+    no_ack: bool,  // gdb asked to stop acknowledging packets
+    running: bool, // gdb is waiting for the machine to stop
+    closed: bool,  // gdb detached or killed the session
+}
+
+impl GdbStub {
+    // Listen for gdb on localhost only, the protocol has no authentication
+    pub fn new(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|err| format!("unable to listen for gdb on port {}: {}", port, err))?;
+        println!("Waiting for gdb on 127.0.0.1:{}", port);
+        Ok(GdbStub { listener, client: None })
+    }
+
+    // Accept a debugger and answer its requests, once per frame. The machine is paused when gdb connects
+    // and resumed when it goes away. Returns whether the machine changed and has to be redrawn
+    pub fn poll(&mut self, chip8: &mut Chip8, osd: &mut Osd) -> bool {
+        if self.client.is_none() {
+            match self.listener.accept().and_then(|(stream, address)| Client::new(stream).map(|client| (client, address))) {
+                Ok((client, address)) => {
+                    chip8.pause();
+                    self.client = Some(client);
+                    osd.message(format!("Debugger connected from {}", address));
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return false,
+                Err(err) => {
+                    osd.error(format!("Unable to accept gdb: {}", err));
+                    return false;
+                }
+            }
+        }
+        let Some(client) = self.client.as_mut() else { return false };
+        match client.poll(chip8) {
+            Ok(changed) if !client.closed => changed,
+            result => {
+                if let Err(err) = result {
+                    if err.kind() != ErrorKind::UnexpectedEof {
+                        osd.error(format!("Lost the connection to gdb: {}", err));
+                    }
+                }
+                self.client = None;
+                chip8.breakpoints.clear();
+                chip8.resume();
+                osd.message("Debugger disconnected");
+                true
+            }
+        }
+    }
+}
+
+impl Client {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Client { stream, input: Vec::new(), no_ack: false, running: false, closed: false })
+    }
+
+    fn poll(&mut self, chip8: &mut Chip8) -> io::Result<bool> {
+        // Take whatever has arrived without blocking the frame loop
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.input.extend_from_slice(&buffer[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut changed = false;
+        while let Some(packet) = self.next_packet()? {
+            changed |= self.handle(&packet, chip8)?;
+            if self.closed {
+                return Ok(changed);
+            }
+        }
+
+        // The frame loop stopped at a breakpoint, or the user paused the game
+        if self.running && chip8.is_paused() {
+            self.running = false;
+            let stop = if chip8.breakpoints.contains(&chip8.pc) { STOP_TRAP } else { STOP_INTERRUPT };
+            self.send(stop)?;
+        }
+        Ok(changed)
+    }
+
+    // Take the next complete packet from the input, acknowledging it. Ctrl-C is returned as a packet of its
+    // own, a bare 0x03
+    fn next_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let packet = match take_packet(&mut self.input) {
+                None => return Ok(None),
+                Some(Incoming::Interrupt) => return Ok(Some(vec![0x03])),
+                Some(packet) => packet,
+            };
+            if !self.no_ack {
+                self.stream.write_all(if packet == Incoming::Corrupt { b"-" } else { b"+" })?;
+            }
+            if let Incoming::Packet(data) = packet {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    // Answer one packet. Returns whether the machine changed
+    fn handle(&mut self, packet: &[u8], chip8: &mut Chip8) -> io::Result<bool> {
+        if packet == [0x03] {
+            // The stop reply goes out once the frame loop sees the pause
+            chip8.pause();
+            return Ok(false);
+        }
+        let packet = String::from_utf8_lossy(packet);
+        let (command, args) = if packet.is_char_boundary(1) { packet.split_at(1) } else { ("", &packet[..]) };
+        match command {
+            "c" => {
+                if !set_pc(chip8, args) {
+                    self.send("E01")?;
+                    return Ok(false);
+                }
+                // Resuming steps off a breakpoint at pc, so the machine doesn't stop on it straight away
+                chip8.resume();
+                self.running = true;
+                Ok(true)
+            }
+            "D" => {
+                self.send("OK")?;
+                self.closed = true;
+                Ok(true)
+            }
+            "k" => {
+                self.closed = true;
+                Ok(true)
+            }
+            "Q" if args == "StartNoAckMode" => {
+                self.send("OK")?;
+                self.no_ack = true;
+                Ok(false)
+            }
+            _ => {
+                let (reply, changed) = answer(command, args, chip8);
+                self.send(&reply)?;
+                Ok(changed)
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        self.stream.write_all(&frame(data.as_bytes()))
+    }
+}
+
+// What arrived from gdb
+#[derive(Debug, PartialEq, Eq)]
+enum Incoming {
+    Packet(Vec<u8>), // With escapes and run lengths decoded
+    Corrupt,         // The checksum didn't match, gdb sends it again
+    Interrupt,       // Ctrl-C
+}
+
+// Take the next `$data#checksum` packet or Ctrl-C byte off the front of `input`, or None if there isn't a
+// complete one yet. Acknowledgements from gdb need no answer and are dropped
+fn take_packet(input: &mut Vec<u8>) -> Option<Incoming> {
+    let Some(start) = input.iter().position(|&byte| byte == b'$' || byte == 0x03) else {
+        input.clear();
+        return None;
+    };
+    if input[start] == 0x03 {
+        input.drain(..=start);
+        return Some(Incoming::Interrupt);
+    }
+    let end = start + input[start..].iter().position(|&byte| byte == b'#')?;
+    if input.len() < end + 3 {
+        return None;
+    }
+    let data = input[start + 1..end].to_vec();
+    let checksum = std::str::from_utf8(&input[end + 1..end + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    input.drain(..end + 3);
+    // The checksum covers the data as sent
+    match checksum == Some(checksum_of(&data)) {
+        true => decode(&data).map_or(Some(Incoming::Corrupt), |data| Some(Incoming::Packet(data))),
+        false => Some(Incoming::Corrupt),
+    }
+}
+
+// Undo the escaping of `#$}*` as `}` and the byte xor 0x20, and expand run lengths: `x*n` repeats x another
+// n - 29 times. None if an escape or run length is cut short
+fn decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut bytes = data.iter().copied();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'}' => decoded.push(bytes.next()? ^ 0x20),
+            b'*' => {
+                let last = *decoded.last()?;
+                let count = bytes.next()?.checked_sub(29)?;
+                decoded.extend(std::iter::repeat_n(last, count as usize));
+            }
+            _ => decoded.push(byte),
+        }
+    }
+    Some(decoded)
+}
+
+// Wrap a reply as `$data#checksum`, escaping the bytes that would end the packet or look like a run length
+fn frame(data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![b'$'];
+    for &byte in data {
+        if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+            packet.extend_from_slice(&[b'}', byte ^ 0x20]);
+        } else {
+            packet.push(byte);
+        }
+    }
+    let checksum = checksum_of(&packet[1..]);
+    packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+    packet
+}
+
+// Answer the packets that only look at or change the machine. Returns the reply and whether the machine
+// changed
+fn answer(command: &str, args: &str, chip8: &mut Chip8) -> (String, bool) {
+    let error = || ("E01".to_string(), false);
+    match command {
+        "?" => (STOP_TRAP.to_string(), false),
+        "g" => ((0..REGISTER_COUNT).map(|n| register_hex(chip8, n)).collect(), false),
+        "G" => {
+            // Check every register before changing any
+            let Some(bytes) = parse_hex_bytes(args) else { return error() };
+            let mut values = Vec::new();
+            let mut offset = 0;
+            for n in 0..REGISTER_COUNT {
+                let size = register_size(n);
+                if let Some(value) = bytes.get(offset..offset + size) {
+                    match register_value(n, value) {
+                        Some(value) => values.push((n, value)),
+                        None => return error(),
+                    }
+                }
+                offset += size;
+            }
+            for (n, value) in values {
+                set_register(chip8, n, value);
+            }
+            ("OK".to_string(), true)
+        }
+        "p" => match usize::from_str_radix(args, 16) {
+            Ok(n) if n < REGISTER_COUNT => (register_hex(chip8, n), false),
+            _ => error(),
+        },
+        "P" => {
+            let register = args.split_once('=').and_then(|(n, value)| {
+                let n = usize::from_str_radix(n, 16).ok().filter(|&n| n < REGISTER_COUNT)?;
+                let value = parse_hex_bytes(value).filter(|value| value.len() == register_size(n))?;
+                Some((n, register_value(n, &value)?))
+            });
+            match register {
+                Some((n, value)) => {
+                    set_register(chip8, n, value);
+                    ("OK".to_string(), true)
+                }
+                None => error(),
+            }
+        }
+        "m" => match parse_range(args, chip8) {
+            Some(range) => (chip8.memory[range].iter().map(|byte| format!("{:02x}", byte)).collect(), false),
+            None => error(),
+        },
+        "M" => {
+            let write = args.split_once(':').and_then(|(range, data)| Some((parse_range(range, chip8)?, parse_hex_bytes(data)?)));
+            match write {
+                Some((range, data)) if range.len() == data.len() => {
+                    for (address, value) in range.zip(data) {
+                        chip8.poke(address as u16, value);
+                    }
+                    ("OK".to_string(), true)
+                }
+                _ => error(),
+            }
+        }
+        // Software and hardware breakpoints are the same thing here
+        "Z" | "z" => match parse_breakpoint(args) {
+            Some(address) if command == "Z" => {
+                chip8.breakpoints.insert(address);
+                ("OK".to_string(), false)
+            }
+            Some(address) => {
+                chip8.breakpoints.remove(&address);
+                ("OK".to_string(), false)
+            }
+            None => (String::new(), false),
+        },
+        "s" => {
+            if !set_pc(chip8, args) {
+                return error();
+            }
+            chip8.tick();
+            (STOP_TRAP.to_string(), true)
+        }
+        // There is a single thread
+        "H" | "T" => ("OK".to_string(), false),
+        "q" => (query(args), false),
+        // Unsupported packets get an empty reply, gdb falls back to the basic ones
+        _ => (String::new(), false),
+    }
+}
+
+// Answer a `q` query, without the leading q
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string();
+    }
+    if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        // Served in chunks: `m` when there is more to read, `l` for the last one
+        let Some((offset, length)) = range.split_once(',') else { return "E01".to_string() };
+        let (Ok(offset), Ok(length)) = (usize::from_str_radix(offset, 16), usize::from_str_radix(length, 16)) else {
+            return "E01".to_string();
+        };
+        let start = offset.min(TARGET_XML.len());
+        let end = (start + length).min(TARGET_XML.len());
+        let more = if end < TARGET_XML.len() { "m" } else { "l" };
+        return format!("{}{}", more, &TARGET_XML[start..end]);
+    }
+    match args {
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        "Offsets" => "Text=0;Data=0;Bss=0".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn register_size(n: usize) -> usize {
+    if n < V0 { 2 } else { 1 }
+}
+
+// Registers are sent as little endian hex
+fn register_hex(chip8: &Chip8, n: usize) -> String {
+    match n {
+        PC => format!("{:04x}", chip8.pc.swap_bytes()),
+        I => format!("{:04x}", chip8.i_register.swap_bytes()),
+        SP => format!("{:04x}", chip8.stack_ptr.swap_bytes()),
+        _ => format!("{:02x}", chip8.v_register[n - V0]),
+    }
+}
+
+// The value of register `n` from its little endian bytes, or None if the machine can't run with it: PC has
+// to leave room for a whole instruction, I has to point into memory and the stack has 16 entries
+fn register_value(n: usize, value: &[u8]) -> Option<u16> {
+    let value = match value {
+        [low, high] => u16::from_le_bytes([*low, *high]),
+        [byte] => *byte as u16,
+        _ => return None,
+    };
+    let valid = match n {
+        PC => value <= MAX_PC,
+        I => value <= MAX_I,
+        SP => value <= 16,
+        _ => true,
+    };
+    valid.then_some(value)
+}
+
+fn set_register(chip8: &mut Chip8, n: usize, value: u16) {
+    match n {
+        PC => chip8.pc = value,
+        I => chip8.i_register = value,
+        SP => chip8.stack_ptr = value,
+        _ => chip8.v_register[n - V0] = value as u8,
+    }
+}
+
+// `s` and `c` can resume somewhere else. Returns false if the address isn't somewhere the machine can run
+fn set_pc(chip8: &mut Chip8, address: &str) -> bool {
+    if address.is_empty() {
+        return true;
+    }
+    match u16::from_str_radix(address, 16) {
+        Ok(address) if address <= MAX_PC => {
+            chip8.pc = address;
+            true
+        }
+        _ => false,
+    }
+}
+
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// `addr,length`, which has to lie inside the CHIP-8 memory
+fn parse_range(args: &str, chip8: &Chip8) -> Option<std::ops::Range<usize>> {
+    let (address, length) = args.split_once(',')?;
+    let start = usize::from_str_radix(address, 16).ok()?;
+    let end = start.checked_add(usize::from_str_radix(length, 16).ok()?)?;
+    (end <= chip8.memory.len()).then_some(start..end)
+}
+
+// `type,addr,kind` for software (0) and hardware (1) breakpoints. Watchpoints aren't supported
+fn parse_breakpoint(args: &str) -> Option<u16> {
+    let mut fields = args.split(',');
+    let kind = fields.next()?;
+    if kind != "0" && kind != "1" {
+        return None;
+    }
+    u16::from_str_radix(fields.next()?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packets(input: &[u8]) -> Vec<Incoming> {
+        let mut input = input.to_vec();
+        std::iter::from_fn(|| take_packet(&mut input)).collect()
+    }
+
+    #[test]
+    fn framing_and_checksums() {
+        assert_eq!(packets(b"+$g#67"), [Incoming::Packet(b"g".to_vec())]);
+        assert_eq!(packets(b"$g#68$?#3f"), [Incoming::Corrupt, Incoming::Packet(b"?".to_vec())]);
+        assert_eq!(packets(b"\x03$s#73"), [Incoming::Interrupt, Incoming::Packet(b"s".to_vec())]);
+
+        // A packet cut short waits for the rest
+        let mut input = b"$m200,4#".to_vec();
+        assert_eq!(take_packet(&mut input), None);
+        input.extend_from_slice(b"5f");
+        assert_eq!(take_packet(&mut input), Some(Incoming::Packet(b"m200,4".to_vec())));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn escapes_and_run_lengths() {
+        assert_eq!(decode(b"a}\x03b"), Some(b"a#b".to_vec()));
+        // '0' then * and ' ' (32): 3 more
+        assert_eq!(decode(b"0* 1"), Some(b"00001".to_vec()));
+        assert_eq!(decode(b"ab}"), None);
+        assert_eq!(decode(b"*!"), None);
+
+        assert_eq!(frame(b"OK"), b"$OK#9a");
+        assert_eq!(frame(b"a#b*"), b"$a}\x03b}\x0a#ca");
+        let mut input = frame(b"$}*#");
+        assert_eq!(take_packet(&mut input), Some(Incoming::Packet(b"$}*#".to_vec())));
+    }
+
+    #[test]
+    fn memory_packets() {
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x12, 0x34, 0xAB, 0xCD]);
+        assert_eq!(answer("m", "200,4", &mut chip8), ("1234abcd".to_string(), false));
+        assert_eq!(answer("m", "ffe,2", &mut chip8).0, "0000");
+        assert_eq!(answer("m", "fff,2", &mut chip8).0, "E01");
+        assert_eq!(answer("m", "200", &mut chip8).0, "E01");
+
+        assert_eq!(answer("M", "300,2:beef", &mut chip8), ("OK".to_string(), true));
+        assert_eq!(chip8.memory[0x300..0x302], [0xBE, 0xEF]);
+        assert_eq!(chip8.take_writes()[0x300..0x303], [true, true, false]);
+        // The length has to match the data, and the range has to fit
+        assert_eq!(answer("M", "300,3:beef", &mut chip8).0, "E01");
+        assert_eq!(answer("M", "fff,2:beef", &mut chip8).0, "E01");
+    }
+
+    #[test]
+    fn breakpoint_packets() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(answer("Z", "0,22a,2", &mut chip8).0, "OK");
+        assert_eq!(answer("Z", "1,230,2", &mut chip8).0, "OK");
+        assert!(chip8.breakpoints.contains(&0x22A) && chip8.breakpoints.contains(&0x230));
+        assert_eq!(answer("z", "0,22a,2", &mut chip8).0, "OK");
+        assert!(!chip8.breakpoints.contains(&0x22A));
+        // Watchpoints aren't supported
+        assert_eq!(answer("Z", "2,300,1", &mut chip8).0, "");
+    }
+
+    #[test]
+    fn registers_the_machine_cant_run_with_are_refused() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(answer("P", "0=fe0f", &mut chip8).0, "OK");
+        assert_eq!(chip8.pc, 0xFFE);
+        assert_eq!(answer("P", "0=ff0f", &mut chip8).0, "E01");
+        assert_eq!(answer("P", "1=0010", &mut chip8).0, "E01");
+        assert_eq!(answer("P", "2=1000", &mut chip8).0, "OK");
+        assert_eq!(answer("P", "2=1100", &mut chip8).0, "E01");
+        assert_eq!(answer("s", "fff", &mut chip8).0, "E01");
+        assert_eq!(chip8.pc, 0xFFE);
+
+        // G changes nothing if any register is bad
+        let mut registers = answer("g", "", &mut chip8).0;
+        registers.replace_range(0..4, "0010");
+        registers.replace_range(12..14, "ff");
+        assert_eq!(answer("G", &registers, &mut chip8).0, "E01");
+        assert_eq!((chip8.pc, chip8.v_register[0]), (0xFFE, 0));
+        registers.replace_range(0..4, "0002");
+        assert_eq!(answer("G", &registers, &mut chip8).0, "OK");
+        assert_eq!((chip8.pc, chip8.i_register), (0x200, 0));
+    }
+}
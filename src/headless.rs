@@ -0,0 +1,73 @@
+use std::path::Path;
+use std::process;
+
+use crate::cli::HeadlessArgs;
+use crate::display;
+use crate::loader;
+use crate::recorder::FrameRecorder;
+use crate::screenshot::{self, ScreenshotFormat};
+use crate::wav::{WavRecorder, WavRecording};
+
+// Run a ROM for a fixed number of frames without a window or sound device, for scripting and testing.
+// Frames run back to back, recordings are still timed at 60Hz
+pub fn run(args: HeadlessArgs) -> Result<(), String> {
+    let machine = &args.machine;
+    let rom = machine.rom.as_ref().ok_or("headless mode needs a ROM")?;
+    let config = machine.load_config()?;
+    let scale = machine.scale(&config);
+
+    let mut chip8 = loader::new_machine(machine)?;
+    let settings = loader::open_rom(&mut chip8, Path::new(rom), &machine.overrides(), &config)?;
+
+    let wav_recording = match &machine.record_audio {
+        Some(path) => {
+            let recorder = WavRecorder::new(path, machine.audio_config(&config))
+                .map_err(|err| format!("unable to create {}: {}", path, err))?;
+            Some(WavRecording::start(&mut chip8.timer, recorder))
+        }
+        None => None,
+    };
+    let mut frame_recorder = machine
+        .record_video
+        .as_ref()
+        .map(|path| FrameRecorder::new(path, settings.palette, scale));
+    if let Some(recorder) = frame_recorder.as_mut() {
+        recorder.capture(0, &chip8.screen);
+    }
+
+    // Stops early if a script asks to exit
+    let mut frames = 0;
+    while frames < args.frames && chip8.quit_requested().is_none() {
+        chip8.run_frame(settings.tickrate);
+        frames += 1;
+        if chip8.draw_flag {
+            if let Some(recorder) = frame_recorder.as_mut() {
+                recorder.capture(frames, &chip8.screen);
+            }
+        }
+    }
+
+    config.save_rpl_flags(&settings.sha1, &chip8.rpl_flags)?;
+    if let Some(recording) = &wav_recording {
+        recording.finish().map_err(|err| format!("unable to save audio recording: {}", err))?;
+    }
+    if let Some(recorder) = frame_recorder {
+        let path = recorder.path().to_string();
+        recorder.finish(frames).map_err(|err| format!("unable to save {}: {}", path, err))?;
+        println!("Saved recording to {}", path);
+    }
+    if let Some(path) = &args.screenshot {
+        screenshot::save_png(&chip8.screen, path, ScreenshotFormat::Color { palette: settings.palette, scale })?;
+        println!("Saved screenshot to {}", path);
+    }
+    if args.print_screen {
+        for line in display::to_text(&chip8.screen) {
+            println!("{}", line);
+        }
+    }
+    // Everything is saved by now, so the script's status can end the process
+    match chip8.quit_requested() {
+        Some(code) if code != 0 => process::exit(code),
+        _ => Ok(()),
+    }
+}
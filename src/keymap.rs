@@ -0,0 +1,98 @@
+// Mapping between keyboard keys and the CHIP-8 keypad.
+// Keys are matched on scan codes (set 1) so the layout stays the same on any keyboard layout.
+
+use std::fs;
+
+// Names accepted in keymap files, with their scan codes. Based on a US QWERTY keyboard
+const KEY_NAMES: [(&str, u32); 47] = [
+    ("1", 0x02), ("2", 0x03), ("3", 0x04), ("4", 0x05), ("5", 0x06),
+    ("6", 0x07), ("7", 0x08), ("8", 0x09), ("9", 0x0A), ("0", 0x0B),
+    ("-", 0x0C), ("=", 0x0D),
+    ("Q", 0x10), ("W", 0x11), ("E", 0x12), ("R", 0x13), ("T", 0x14),
+    ("Y", 0x15), ("U", 0x16), ("I", 0x17), ("O", 0x18), ("P", 0x19),
+    ("[", 0x1A), ("]", 0x1B),
+    ("A", 0x1E), ("S", 0x1F), ("D", 0x20), ("F", 0x21), ("G", 0x22),
+    ("H", 0x23), ("J", 0x24), ("K", 0x25), ("L", 0x26),
+    (";", 0x27), ("'", 0x28), ("`", 0x29), ("\\", 0x2B),
+    ("Z", 0x2C), ("X", 0x2D), ("C", 0x2E), ("V", 0x2F), ("B", 0x30),
+    ("N", 0x31), ("M", 0x32),
+    (",", 0x33), (".", 0x34), ("/", 0x35),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    scancodes: [u32; 16], // Scan code for each CHIP-8 key
+}
+
+impl Default for Keymap {
+    // 1,2,3,4   <= keyboard, chip8 =>  1,2,3,c
+    // q,w,e,r                          4,5,6,d
+    // a,s,d,f                          7,8,9,e
+    // z,x,c,v                          a,0,b,f
+    fn default() -> Self {
+        Keymap {
+            scancodes: [
+                0x2d, 0x02, 0x03, 0x04, // 0 <=> x, 1 <=> 1, 2 <=> 2, 3 <=> 3
+                0x10, 0x11, 0x12, 0x1e, // 4 <=> q, 5 <=> w, 6 <=> e, 7 <=> a
+                0x1f, 0x20, 0x2c, 0x2e, // 8 <=> s, 9 <=> d, a <=> z, b <=> c
+                0x05, 0x13, 0x21, 0x2f, // c <=> 4, d <=> r, e <=> f, f <=> v
+            ],
+        }
+    }
+}
+
+impl Keymap {
+    // Read a keymap file. Each line maps a CHIP-8 key to a keyboard key, like `5 = W` or `a = 0x2c`.
+    // Keys can be given by name or by scan code, blank lines and lines starting with # are ignored.
+    // CHIP-8 keys that aren't listed keep their default key.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("unable to read keymap {}: {}", path, err))?;
+        Self::parse(&contents).map_err(|err| format!("invalid keymap {}: {}", path, err))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut keymap = Keymap::default();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (chip8_key, key) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `<chip-8 key> = <key>`", number + 1))?;
+            let chip8_key = u8::from_str_radix(chip8_key.trim(), 16)
+                .ok()
+                .filter(|key| *key < 16)
+                .ok_or_else(|| format!("line {}: '{}' is not a CHIP-8 key (0-f)", number + 1, chip8_key.trim()))?;
+            let scancode = parse_key(key.trim())
+                .ok_or_else(|| format!("line {}: unknown key '{}'", number + 1, key.trim()))?;
+            keymap.scancodes[chip8_key as usize] = scancode;
+        }
+        Ok(keymap)
+    }
+
+    // CHIP-8 key for a keyboard scan code
+    pub fn chip8_key(&self, scancode: u32) -> Option<usize> {
+        self.scancodes.iter().position(|code| *code == scancode)
+    }
+
+    // Name of the keyboard key bound to a CHIP-8 key, for showing controls
+    pub fn label(&self, chip8_key: u8) -> String {
+        let scancode = self.scancodes[chip8_key as usize & 0xF];
+        KEY_NAMES
+            .iter()
+            .find(|(_, code)| *code == scancode)
+            .map_or(format!("{:#04x}", scancode), |(name, _)| name.to_string())
+    }
+}
+
+// Key name or scan code, e.g. "W" or "0x11"
+fn parse_key(key: &str) -> Option<u32> {
+    if let Some(hex) = key.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    KEY_NAMES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, code)| *code)
+}
@@ -0,0 +1,13 @@
+// The emulator core: the CHIP-8 interpreter, its timers, beeper tone and cheats, the low-level COSMAC VIP and the
+// reinforcement learning environment, with no window, sound device or files. The `rust-chip8` binary builds the
+// frontend on top, and the Python bindings in `python/`, the C API in `capi/` and the libretro core in `libretro/`
+// embed it
+pub mod chip8;
+pub mod timer;
+pub mod timing;
+pub mod cdp1802;
+pub mod vip;
+pub mod display;
+pub mod tone;
+pub mod cheat;
+pub mod env;
@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::Path;
+
+use crate::chip8::{Chip8, Quirks, DEFAULT_TICKRATE};
+use crate::cli::MachineArgs;
+use crate::config::Config;
+use crate::database::{self, RomInfo};
+use crate::display::Palette;
+use crate::keymap::Keymap;
+use crate::osd::Osd;
+use crate::script::Script;
+use crate::timing::Timing;
+use crate::vip::Vip;
+
+// Settings for running a ROM, each one left unset to fall back to the next source
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Overrides {
+    pub palette: Option<Palette>,
+    pub quirks: Option<Quirks>,
+    pub tickrate: Option<u32>,
+    pub timing: Option<Timing>,
+    pub speed: Option<f64>,
+}
+
+impl Overrides {
+    // Fill in the settings missing here from `other`
+    pub fn or(self, other: Overrides) -> Overrides {
+        Overrides {
+            palette: self.palette.or(other.palette),
+            quirks: self.quirks.or(other.quirks),
+            tickrate: self.tickrate.or(other.tickrate),
+            timing: self.timing.or(other.timing),
+            speed: self.speed.or(other.speed),
+        }
+    }
+}
+
+// How to run the ROM that was just started
+pub struct RomSettings {
+    pub tickrate: u32,
+    pub palette: Palette,
+    pub speed: f64,
+    pub title: String,
+    pub sha1: String,
+    pub info: Option<RomInfo>,
+}
+
+// Create a machine with the seed, load address, VIP images and script from the command line
+pub fn new_machine(args: &MachineArgs) -> Result<Chip8, String> {
+    let mut chip8 = Chip8::new();
+    chip8.set_load_address(args.load_address);
+    if let Some(seed) = args.seed {
+        chip8.seed(seed);
+    }
+    if let (Some(interpreter), Some(monitor)) = (&args.vip_interpreter, &args.vip_monitor) {
+        let read = |path: &String| fs::read(path).map_err(|err| format!("Unable to load {}: {}", path, err));
+        chip8.attach_vip(Vip::new(&read(interpreter)?, &read(monitor)?)?);
+    }
+    if let Some(path) = &args.script {
+        Script::load(Path::new(path))?.attach(&mut chip8);
+    }
+    Ok(chip8)
+}
+
+// Read the ROM at `path` and start it, see `start_rom`
+pub fn open_rom(chip8: &mut Chip8, path: &Path, overrides: &Overrides, config: &Config) -> Result<RomSettings, String> {
+    fs::read(path)
+        .map_err(|err| err.to_string())
+        .and_then(|rom| start_rom(chip8, &rom, overrides, config))
+        .map_err(|err| format!("Unable to load {}: {}", path.display(), err))
+}
+
+// Reset the machine and load the ROM. Settings come from the command line, then the settings saved
+// for the ROM, then the database if the ROM is known, then the user's defaults.
+// The running game is left alone if the ROM can't be loaded
+pub fn start_rom(chip8: &mut Chip8, rom: &[u8], overrides: &Overrides, config: &Config) -> Result<RomSettings, String> {
+    chip8.load(rom)?;
+    chip8.resume();
+
+    let sha1 = database::sha1_hex(rom);
+    chip8.rpl_flags = config.load_rpl_flags(&sha1);
+    chip8.cheats = config.load_cheats(&sha1);
+    let info = database::lookup(rom);
+    let known = info.as_ref().map_or(Overrides::default(), |info| Overrides {
+        palette: info.palette,
+        quirks: Some(info.quirks),
+        tickrate: Some(info.tickrate),
+        ..Overrides::default()
+    });
+    let settings = overrides.or(config.overrides(&sha1)).or(known).or(config.defaults);
+    chip8.quirks = settings.quirks.unwrap_or_default();
+    chip8.timing = settings.timing.unwrap_or_default();
+    let tickrate = settings.tickrate.unwrap_or(DEFAULT_TICKRATE);
+    let palette = settings.palette.unwrap_or_default();
+    let speed = settings.speed.unwrap_or(1.0);
+    let title = info.as_ref().map_or(String::from("Chip-8"), |info| format!("Chip-8 - {}", info.title));
+    Ok(RomSettings { tickrate, palette, speed, title, sha1, info })
+}
+
+// Tell the player what the database knows about the ROM, including which keys do what
+pub fn show_rom_info(info: &RomInfo, keymap: &Keymap, osd: &mut Osd) {
+    osd.message(format!("{} ({}, {} instructions per frame)", info.title, info.platform, info.tickrate));
+    if !info.keys.is_empty() {
+        let hints: Vec<String> = info
+            .keys
+            .iter()
+            .map(|(name, key)| format!("{} = {}", name, keymap.label(*key)))
+            .collect();
+        osd.message(format!("Controls: {}", hints.join(", ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IBM_LOGO: &[u8] = include_bytes!("../roms/IBM Logo.ch8");
+
+    // Instructions per frame picked for `rom`
+    fn tickrate(overrides: Overrides, config: &Config, rom: &[u8]) -> u32 {
+        start_rom(&mut Chip8::new(), rom, &overrides, config).unwrap().tickrate
+    }
+
+    #[test]
+    fn settings_precedence() {
+        let mut config = Config::default();
+        let unknown = [0x12, 0x00];
+        // The database runs the IBM logo at the original platform's 15 instructions per frame
+        assert_eq!(tickrate(Overrides::default(), &config, IBM_LOGO), 15);
+        assert_eq!(tickrate(Overrides::default(), &config, &unknown), DEFAULT_TICKRATE);
+
+        // The user's defaults only fill in for what the database doesn't know
+        config.defaults = Overrides { tickrate: Some(20), quirks: Some("modern".parse().unwrap()), ..Overrides::default() };
+        assert_eq!(tickrate(Overrides::default(), &config, IBM_LOGO), 15);
+        assert_eq!(tickrate(Overrides::default(), &config, &unknown), 20);
+        let mut chip8 = Chip8::new();
+        start_rom(&mut chip8, IBM_LOGO, &Overrides::default(), &config).unwrap();
+        assert_eq!(chip8.quirks, "original".parse().unwrap());
+
+        // Settings saved for the ROM win over the database
+        let sha1 = database::sha1_hex(IBM_LOGO);
+        config.update_rom(&sha1, "IBM Logo", |rom| rom.tickrate = Some(30)).unwrap();
+        assert_eq!(tickrate(Overrides::default(), &config, IBM_LOGO), 30);
+
+        // And the command line wins over everything
+        let command_line = Overrides { tickrate: Some(40), ..Overrides::default() };
+        assert_eq!(tickrate(command_line, &config, IBM_LOGO), 40);
+        assert_eq!(tickrate(command_line, &config, &unknown), 40);
+    }
+}
@@ -1,203 +1,78 @@
-use std::env;
-use std::fs::File;
-use std::io::Read;
-use std::time::{Duration, Instant};
+use std::fs;
+use std::process;
 
-use pixels::{Pixels, SurfaceTexture};
-use winit::event::{ElementState, Event, KeyboardInput, WindowEvent};
-use winit::event_loop::EventLoop;
-use winit::window::WindowBuilder;
+use clap::Parser;
 
-use crate::audio::Audio;
-use crate::chip8::Chip8;
+use rust_chip8::{cheat, chip8, display, env, timer, timing, tone, vip};
 
-mod chip8;
-mod timer;
-mod audio;
+use crate::cli::{Cli, Command};
+use crate::keymap::Keymap;
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
+mod audio;
+mod wav;
+mod recorder;
+mod screenshot;
+mod database;
+mod text;
+mod menu;
+mod memview;
+mod cheatview;
+mod osd;
+mod cli;
+mod config;
+mod keymap;
+mod disasm;
+mod loader;
+mod frontend;
+mod headless;
+mod gdb;
+mod rpc;
+mod script;
 
 fn main() {
-    let event_loop = EventLoop::new();
-    let window = {
-        WindowBuilder::new()
-            .with_title("Chip-8")
-            .build(&event_loop)
-            .unwrap()
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Some(Command::Run(args)) => frontend::run(args),
+        None => frontend::run(cli.run),
+        Some(Command::Disasm { rom, load_address }) => disasm(&rom, load_address),
+        Some(Command::Info { rom }) => info(&rom),
+        Some(Command::Headless(args)) => headless::run(args),
+        Some(Command::Serve(args)) => rpc::run(args),
     };
-    let mut pixels = {
-        let window_size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
-    };
-    let audio_stream = Audio::new();
-    let mut chip8 = Chip8::new();
-    let mut rom = File::open("roms/IBM Logo.ch8").expect("Unable to open file");
-    let args: Vec<String> = env::args().collect();
-    if args.len() >= 2 {
-        rom = File::open(&args[1]).expect("Unable to open file");
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
     }
-    let mut rom_buf = Vec::new();
-    rom.read_to_end(&mut rom_buf).unwrap();
-    chip8.load(&rom_buf);
-
-    let timer_length = Duration::new(0, 16666666); // This is 60Hz
-    event_loop.run(move |event, _, control_flow| {
-        if chip8.draw_flag {
-            window.request_redraw();
-        }
-
-        if chip8.timer.get_st() != 0 {
-            audio_stream.play();
-        } else {
-            audio_stream.pause();
-        }
+}
 
-        match event {
-            Event::WindowEvent {
-                event:
-                WindowEvent::KeyboardInput {
-                    input:
-                    KeyboardInput {
-                        scancode: key,
-                        state,
-                        ..
-                    },
-                    ..
-                },
-                ..
-            } => match state {
-                // 1,2,3,4   <= keyboard, chip8 =>  1,2,3,c
-                // q,w,e,r                          4,5,6,d
-                // a,s,d,f                          7,8,9,e
-                // z,x,c,v                          a,0,b,f
+fn read_rom(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("unable to read {}: {}", path, err))
+}
 
-                // Use keyboard scan codes set 1
-                ElementState::Pressed => match key {
-                    0x02 => { // 1 <=> 1
-                        chip8.key[0x1] = true;
-                    }
-                    0x03 => { // 2 <=> 2
-                        chip8.key[0x2] = true;
-                    }
-                    0x04 => { // 3 <=> 3
-                        chip8.key[0x3] = true;
-                    }
-                    0x05 => { // 4 <=> c
-                        chip8.key[0xC] = true;
-                    }
-                    0x10 => { // q <=> 4
-                        chip8.key[0x4] = true;
-                    }
-                    0x11 => { // w <=> 5
-                        chip8.key[0x5] = true;
-                    }
-                    0x12 => { // e <=> 6
-                        chip8.key[0x6] = true;
-                    }
-                    0x13 => { // r <=> d
-                        chip8.key[0xD] = true;
-                    }
-                    0x1e => { // a <=> 7
-                        chip8.key[0x7] = true;
-                    }
-                    0x1f => { // s <=> 8
-                        chip8.key[0x8] = true;
-                    }
-                    0x20 => { // d <=> 9
-                        chip8.key[0x9] = true;
-                    }
-                    0x21 => { // f <=> e
-                        chip8.key[0xE] = true;
-                    }
-                    0x2c => { // z <=> a
-                        chip8.key[0xA] = true;
-                    }
-                    0x2d => { // x <=> 0
-                        chip8.key[0x0] = true;
-                    }
-                    0x2e => { // c <=> b
-                        chip8.key[0xB] = true;
-                    }
-                    0x2f => { // v <=> f
-                        chip8.key[0xF] = true;
-                    }
-                    _ => (),
-                }
-                ElementState::Released => match key {
-                    0x02 => { // 1 <=> 1
-                        chip8.key[0x1] = false;
-                    }
-                    0x03 => { // 2 <=> 2
-                        chip8.key[0x2] = false;
-                    }
-                    0x04 => { // 3 <=> 3
-                        chip8.key[0x3] = false;
-                    }
-                    0x05 => { // 4 <=> c
-                        chip8.key[0xC] = false;
-                    }
-                    0x10 => { // q <=> 4
-                        chip8.key[0x4] = false;
-                    }
-                    0x11 => { // w <=> 5
-                        chip8.key[0x5] = false;
-                    }
-                    0x12 => { // e <=> 6
-                        chip8.key[0x6] = false;
-                    }
-                    0x13 => { // r <=> d
-                        chip8.key[0xD] = false;
-                    }
-                    0x1e => { // a <=> 7
-                        chip8.key[0x7] = false;
-                    }
-                    0x1f => { // s <=> 8
-                        chip8.key[0x8] = false;
-                    }
-                    0x20 => { // d <=> 9
-                        chip8.key[0x9] = false;
-                    }
-                    0x21 => { // f <=> e
-                        chip8.key[0xE] = false;
-                    }
-                    0x2c => { // z <=> a
-                        chip8.key[0xA] = false;
-                    }
-                    0x2d => { // x <=> 0
-                        chip8.key[0x0] = false;
-                    }
-                    0x2e => { // c <=> b
-                        chip8.key[0xB] = false;
-                    }
-                    0x2f => { // v <=> f
-                        chip8.key[0xF] = false;
-                    }
-                    _ => (),
-                }
-            }
-            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
-                control_flow.set_exit();
-            }
-            Event::RedrawRequested(_) => {
-                // Use set_wait_until to draw at 60 fps
-                control_flow.set_wait_until(Instant::now() + timer_length);
+fn disasm(path: &str, load_address: u16) -> Result<(), String> {
+    let rom = read_rom(path)?;
+    for line in disasm::listing(&rom, load_address) {
+        println!("{}", line);
+    }
+    Ok(())
+}
 
-                let frame = pixels.get_frame_mut();
-                for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-                    let pixel_state = chip8.screen[i];
-                    let rgba = if pixel_state {
-                        [0x00, 0xFF, 0x00, 0xFF]
-                    } else {
-                        [0x00, 0x00, 0x00, 0xFF]
-                    };
-                    pixel.copy_from_slice(&rgba);
-                }
-                pixels.render().unwrap();
+fn info(path: &str) -> Result<(), String> {
+    let rom = read_rom(path)?;
+    println!("File:     {}", path);
+    println!("Size:     {} bytes", rom.len());
+    println!("SHA-1:    {}", database::sha1_hex(&rom));
+    match database::lookup(&rom) {
+        Some(info) => {
+            println!("Title:    {}", info.title);
+            println!("Platform: {}", info.platform);
+            println!("Tickrate: {} instructions per frame", info.tickrate);
+            println!("Quirks:   {:?}", info.quirks);
+            for (name, key) in &info.keys {
+                println!("Key:      {} = {:X} ({})", name, key, Keymap::default().label(*key));
             }
-            _ => ()
         }
-        chip8.tick();
-    });
+        None => println!("Not in the ROM database"),
+    }
+    Ok(())
 }
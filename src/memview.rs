@@ -0,0 +1,175 @@
+use winit::event::VirtualKeyCode;
+
+use crate::chip8::Chip8;
+use crate::display::Palette;
+use crate::text::{Canvas, CHAR_WIDTH, LINE_HEIGHT};
+
+const MEMORY_SIZE: usize = 4096;
+const BYTES_PER_ROW: usize = 16;
+
+// How many 60Hz frames a byte stays highlighted after the program writes to it
+const WRITE_HIGHLIGHT: u8 = 60;
+
+const PC_COLOR: [u8; 4] = [0x00, 0x70, 0x00, 0xFF];
+const I_COLOR: [u8; 4] = [0x00, 0x40, 0xA0, 0xFF];
+const WRITE_COLOR: [u8; 4] = [0xFF, 0x50, 0x30, 0xFF];
+
+// Hexdump of the CHIP-8 memory with the registers alongside.
+// Bytes can be edited while the game is paused: move with the arrow keys and type two hex digits
+pub struct MemoryViewer {
+    cursor: usize,
+    scroll: usize,        // First row shown
+    pending: Option<u8>,  // High nibble typed so far
+    heat: [u8; MEMORY_SIZE], // Frames left to highlight each byte as recently written
+}
+
+impl MemoryViewer {
+    // Open the viewer with the cursor on the next instruction. Writes made while it was closed are old news,
+    // so they aren't highlighted
+    pub fn new(chip8: &mut Chip8) -> Self {
+        chip8.take_writes();
+        MemoryViewer {
+            cursor: chip8.pc as usize % MEMORY_SIZE,
+            scroll: 0,
+            pending: None,
+            heat: [0; MEMORY_SIZE],
+        }
+    }
+
+    // Keys the viewer uses. The rest still reach the hotkeys, but the game gets no keypad input while it is open
+    pub fn handles(key: VirtualKeyCode) -> bool {
+        hex_digit(key).is_some() || matches!(
+            key,
+            VirtualKeyCode::Up | VirtualKeyCode::Down | VirtualKeyCode::Left | VirtualKeyCode::Right |
+            VirtualKeyCode::PageUp | VirtualKeyCode::PageDown | VirtualKeyCode::Home | VirtualKeyCode::End |
+            VirtualKeyCode::Tab
+        )
+    }
+
+    pub fn key_pressed(&mut self, key: VirtualKeyCode, chip8: &mut Chip8) {
+        let page = BYTES_PER_ROW * 16;
+        let cursor = self.cursor;
+        match key {
+            VirtualKeyCode::Left => self.cursor = cursor.saturating_sub(1),
+            VirtualKeyCode::Right => self.cursor = cursor + 1,
+            VirtualKeyCode::Up => self.cursor = cursor.saturating_sub(BYTES_PER_ROW),
+            VirtualKeyCode::Down => self.cursor = cursor + BYTES_PER_ROW,
+            VirtualKeyCode::PageUp => self.cursor = cursor.saturating_sub(page),
+            VirtualKeyCode::PageDown => self.cursor = cursor + page,
+            VirtualKeyCode::Home => self.cursor = 0,
+            VirtualKeyCode::End => self.cursor = MEMORY_SIZE - 1,
+            VirtualKeyCode::Tab => self.cursor = chip8.pc as usize,
+            _ => {
+                // Editing a running game would race with the program, so it only works while paused
+                if let (Some(digit), true) = (hex_digit(key), chip8.is_paused()) {
+                    match self.pending.take() {
+                        None => self.pending = Some(digit),
+                        Some(high) => {
+                            chip8.poke(cursor as u16, high << 4 | digit);
+                            self.cursor = cursor + 1;
+                        }
+                    }
+                }
+            }
+        }
+        // Moving away drops a half typed byte
+        self.cursor = self.cursor.min(MEMORY_SIZE - 1);
+        if self.cursor != cursor {
+            self.pending = None;
+        }
+    }
+
+    // Pick up the bytes the program wrote since the last call, once per 60Hz frame
+    pub fn update(&mut self, chip8: &mut Chip8) {
+        for (heat, written) in self.heat.iter_mut().zip(chip8.take_writes()) {
+            *heat = if written { WRITE_HIGHLIGHT } else { heat.saturating_sub(1) };
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, palette: &Palette, chip8: &Chip8) {
+        let (width, height) = (canvas.width(), canvas.height());
+        canvas.fill_rect(0, 0, width, height, palette.off);
+
+        let header = if chip8.is_paused() { "Memory (paused, type hex to edit)" } else { "Memory (pause with P to edit)" };
+        canvas.draw_text(CHAR_WIDTH, LINE_HEIGHT / 2, header, palette.on);
+        let footer = "Arrows: move  Tab: go to PC  F4: close";
+        canvas.draw_text(CHAR_WIDTH, height - LINE_HEIGHT, footer, palette.on);
+
+        // Keep the cursor in view
+        let top = LINE_HEIGHT * 2;
+        let rows = (height - top - LINE_HEIGHT * 2) / LINE_HEIGHT;
+        let cursor_row = self.cursor / BYTES_PER_ROW;
+        if cursor_row < self.scroll {
+            self.scroll = cursor_row;
+        } else if cursor_row >= self.scroll + rows {
+            self.scroll = cursor_row + 1 - rows;
+        }
+
+        // Rows look like `0x200  00 e0 a2 2a ...`
+        let pc = chip8.pc as usize;
+        let i = chip8.i_register as usize;
+        for row in 0..rows.min(MEMORY_SIZE / BYTES_PER_ROW - self.scroll) {
+            let y = top + row * LINE_HEIGHT;
+            let start = (self.scroll + row) * BYTES_PER_ROW;
+            canvas.draw_text(CHAR_WIDTH, y, &format!("{:#05x}", start), palette.on);
+            for column in 0..BYTES_PER_ROW {
+                let address = start + column;
+                let x = CHAR_WIDTH * (8 + column * 3);
+                let mut text = format!("{:02x}", chip8.memory[address]);
+                let mut color = if self.heat[address] > 0 { WRITE_COLOR } else { palette.on };
+                if address == pc || address == pc + 1 {
+                    canvas.fill_rect(x - 1, y - 1, CHAR_WIDTH * 2 + 1, LINE_HEIGHT, PC_COLOR);
+                } else if address == i {
+                    canvas.fill_rect(x - 1, y - 1, CHAR_WIDTH * 2 + 1, LINE_HEIGHT, I_COLOR);
+                }
+                if address == self.cursor {
+                    canvas.fill_rect(x - 1, y - 1, CHAR_WIDTH * 2 + 1, LINE_HEIGHT, palette.on);
+                    color = palette.off;
+                    if let Some(high) = self.pending {
+                        text = format!("{:x}_", high);
+                    }
+                }
+                canvas.draw_text(x, y, &text, color);
+            }
+        }
+
+        // Registers to the right of the dump
+        let x = CHAR_WIDTH * (9 + BYTES_PER_ROW * 3);
+        let mut registers: Vec<String> = chip8
+            .v_register
+            .iter()
+            .enumerate()
+            .map(|(n, value)| format!("V{:X} {:02x}", n, value))
+            .collect();
+        registers.push(String::new());
+        registers.push(format!("PC {:#05x}", chip8.pc));
+        registers.push(format!("I  {:#05x}", chip8.i_register));
+        registers.push(format!("SP {}", chip8.stack_ptr));
+        for (row, line) in registers.iter().enumerate() {
+            canvas.draw_text(x, top + row * LINE_HEIGHT, line, palette.on);
+        }
+    }
+}
+
+pub fn hex_digit(key: VirtualKeyCode) -> Option<u8> {
+    let digit = match key {
+        VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => 0x0,
+        VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => 0x1,
+        VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => 0x2,
+        VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => 0x3,
+        VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => 0x4,
+        VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => 0x5,
+        VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => 0x6,
+        VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => 0x7,
+        VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => 0x8,
+        VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => 0x9,
+        VirtualKeyCode::A => 0xA,
+        VirtualKeyCode::B => 0xB,
+        VirtualKeyCode::C => 0xC,
+        VirtualKeyCode::D => 0xD,
+        VirtualKeyCode::E => 0xE,
+        VirtualKeyCode::F => 0xF,
+        _ => return None,
+    };
+    Some(digit)
+}
@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use winit::event::VirtualKeyCode;
+
+use crate::database;
+use crate::display::Palette;
+use crate::text::{Canvas, CHAR_WIDTH, LINE_HEIGHT};
+
+const EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+
+struct Entry {
+    path: PathBuf,
+    name: String,
+    info: Option<String>, // Title and platform from the database
+}
+
+// What the frontend should do after a key press in the menu
+pub enum MenuAction {
+    None,
+    Load(PathBuf),
+    Close,
+}
+
+// On-screen list of the ROMs in a directory
+pub struct RomBrowser {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    selected: usize,
+    scroll: usize,
+}
+
+impl RomBrowser {
+    pub fn new(dir: &Path) -> Self {
+        let mut entries: Vec<Entry> = fs::read_dir(dir)
+            .map(|read_dir| read_dir.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect())
+            .unwrap_or_else(|err| {
+                eprintln!("Unable to read {}: {}", dir.display(), err);
+                Vec::new()
+            })
+            .into_iter()
+            .filter(|path: &PathBuf| {
+                path.is_file() && path.extension().is_some_and(|ext| {
+                    EXTENSIONS.iter().any(|known| ext.eq_ignore_ascii_case(known))
+                })
+            })
+            .map(|path| {
+                let info = fs::read(&path)
+                    .ok()
+                    .and_then(|rom| database::lookup(&rom))
+                    .map(|info| format!("{} ({})", info.title, info.platform));
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                Entry { path, name, info }
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.name.to_lowercase());
+
+        RomBrowser {
+            dir: dir.to_path_buf(),
+            entries,
+            selected: 0,
+            scroll: 0,
+        }
+    }
+
+    // Select the ROM at `path` if it is in the list
+    pub fn select(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.path == path) {
+            self.selected = index;
+        }
+    }
+
+    pub fn key_pressed(&mut self, key: VirtualKeyCode) -> MenuAction {
+        let count = self.entries.len();
+        match key {
+            VirtualKeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            VirtualKeyCode::Down => self.selected = (self.selected + 1).min(count.saturating_sub(1)),
+            VirtualKeyCode::PageUp => self.selected = self.selected.saturating_sub(10),
+            VirtualKeyCode::PageDown => self.selected = (self.selected + 10).min(count.saturating_sub(1)),
+            VirtualKeyCode::Home => self.selected = 0,
+            VirtualKeyCode::End => self.selected = count.saturating_sub(1),
+            VirtualKeyCode::Return => {
+                if let Some(entry) = self.entries.get(self.selected) {
+                    return MenuAction::Load(entry.path.clone());
+                }
+            }
+            VirtualKeyCode::Escape => return MenuAction::Close,
+            _ => (),
+        }
+        MenuAction::None
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas, palette: &Palette) {
+        let (width, height) = (canvas.width(), canvas.height());
+        canvas.fill_rect(0, 0, width, height, palette.off);
+
+        let header = format!("Select a ROM in {}", self.dir.display());
+        canvas.draw_text(CHAR_WIDTH, LINE_HEIGHT / 2, &header, palette.on);
+        let footer = "Up/Down: select  Enter: load  Esc: back";
+        canvas.draw_text(CHAR_WIDTH, height - LINE_HEIGHT, footer, palette.on);
+
+        if self.entries.is_empty() {
+            canvas.draw_text(CHAR_WIDTH, LINE_HEIGHT * 2, "No .ch8, .sc8 or .xo8 files found", palette.on);
+            return;
+        }
+
+        // Keep the selection in view
+        let top = LINE_HEIGHT * 2;
+        let rows = (height - top - LINE_HEIGHT * 2) / LINE_HEIGHT;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        let info_column = 32;
+        for (row, (index, entry)) in self.entries.iter().enumerate().skip(self.scroll).take(rows).enumerate() {
+            let y = top + row * LINE_HEIGHT;
+            let color = if index == self.selected {
+                canvas.fill_rect(0, y - 1, width, LINE_HEIGHT, palette.on);
+                palette.off
+            } else {
+                palette.on
+            };
+            let name: String = entry.name.chars().take(info_column - 2).collect();
+            canvas.draw_text(CHAR_WIDTH, y, &name, color);
+            if let Some(info) = &entry.info {
+                canvas.draw_text(CHAR_WIDTH * info_column, y, info, color);
+            }
+        }
+    }
+}
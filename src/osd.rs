@@ -0,0 +1,184 @@
+// On-screen display drawn over the game: transient messages, errors, frame and instruction rates
+// the pause/speed indicator and a script's HUD. Messages and errors are also printed to the terminal.
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::text::{Canvas, CHAR_WIDTH, GLYPH_HEIGHT, LINE_HEIGHT};
+
+// How long messages and errors stay on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+const ERROR_DURATION: Duration = Duration::from_secs(5);
+
+// Most messages shown at once, older ones are dropped
+const MAX_MESSAGES: usize = 3;
+
+// How often the rates are recalculated
+const SAMPLE_PERIOD: Duration = Duration::from_secs(1);
+
+const TEXT_COLOR: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const BOX_COLOR: [u8; 4] = [0x20, 0x20, 0x20, 0xFF];
+const ERROR_COLOR: [u8; 4] = [0xA0, 0x00, 0x00, 0xFF];
+
+// Which parts of the display are shown. Set in the [osd] table of config.toml, and toggled with hotkeys
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OsdElements {
+    pub fps: bool,      // Emulated frames per second
+    pub ips: bool,      // Instructions per second
+    pub status: bool,   // Paused, speed
+    pub messages: bool,
+    pub errors: bool,
+}
+
+impl Default for OsdElements {
+    fn default() -> Self {
+        OsdElements {
+            fps: false,
+            ips: false,
+            status: true,
+            messages: true,
+            errors: true,
+        }
+    }
+}
+
+pub struct Osd {
+    pub elements: OsdElements,
+    pub hud: Vec<String>, // Lines from the script, under the rates
+    messages: Vec<(String, Instant)>,
+    error: Option<(String, Instant)>,
+    // Machine counters at `sample_start`, turned into rates once per sample period
+    sample_start: Instant,
+    frames: u64,
+    instructions: u64,
+    fps: f64,
+    ips: f64,
+}
+
+impl Osd {
+    pub fn new(elements: OsdElements) -> Self {
+        Osd {
+            elements,
+            hud: Vec::new(),
+            messages: Vec::new(),
+            error: None,
+            sample_start: Instant::now(),
+            frames: 0,
+            instructions: 0,
+            fps: 0.0,
+            ips: 0.0,
+        }
+    }
+
+    // Show a message for a few seconds, e.g. "Saved screenshot to ..."
+    pub fn message(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        println!("{}", text);
+        self.messages.push((text, Instant::now()));
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    // Show an error in a banner along the bottom. Only the latest error is kept
+    pub fn error(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        eprintln!("{}", text);
+        self.error = Some((text, Instant::now()));
+    }
+
+    pub fn clear_error(&mut self) {
+        self.error = None;
+    }
+
+    // Expire old messages and refresh the rates from the machine's frame and instruction counters.
+    // Returns whether the display has to be redrawn
+    pub fn update(&mut self, frames: u64, instructions: u64) -> bool {
+        let had_text = !self.messages.is_empty() || self.error.is_some();
+        self.messages.retain(|(_, shown)| shown.elapsed() < MESSAGE_DURATION);
+        if self.error.as_ref().is_some_and(|(_, shown)| shown.elapsed() >= ERROR_DURATION) {
+            self.error = None;
+        }
+
+        let elapsed = self.sample_start.elapsed();
+        if elapsed >= SAMPLE_PERIOD {
+            self.fps = frames.saturating_sub(self.frames) as f64 / elapsed.as_secs_f64();
+            self.ips = instructions.saturating_sub(self.instructions) as f64 / elapsed.as_secs_f64();
+            self.sample_start = Instant::now();
+            self.frames = frames;
+            self.instructions = instructions;
+        }
+        had_text || self.elements.fps || self.elements.ips
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, paused: bool, speed: f64) {
+        // Rates in the top left corner
+        let mut stats = Vec::new();
+        if self.elements.fps {
+            stats.push(format!("{:.0} FPS", self.fps));
+        }
+        if self.elements.ips {
+            stats.push(format!("{:.0} IPS", self.ips));
+        }
+        let mut top = 2;
+        if !stats.is_empty() {
+            draw_label(canvas, 2, top, &stats.join("  "));
+            top += LINE_HEIGHT + 2;
+        }
+        for line in &self.hud {
+            draw_label(canvas, 2, top, line);
+            top += LINE_HEIGHT + 2;
+        }
+
+        // Pause and speed indicator in the top right corner
+        if self.elements.status {
+            let mut status = Vec::new();
+            if speed.is_infinite() {
+                status.push(String::from("TURBO"));
+            } else if speed != 1.0 {
+                status.push(format!("{}x", speed));
+            }
+            if paused {
+                status.push(String::from("PAUSED"));
+            }
+            if !status.is_empty() {
+                let text = status.join(" ");
+                let x = canvas.width().saturating_sub(text.len() * CHAR_WIDTH + 4);
+                draw_label(canvas, x, 2, &text);
+            }
+        }
+
+        // Errors along the bottom, with messages stacked above them
+        let mut bottom = canvas.height();
+        if let (Some((text, _)), true) = (&self.error, self.elements.errors) {
+            bottom = draw_error(canvas, text);
+        }
+        if self.elements.messages {
+            for (text, _) in self.messages.iter().rev() {
+                bottom -= LINE_HEIGHT + 2;
+                draw_label(canvas, 2, bottom, text);
+            }
+        }
+    }
+}
+
+// Text on a dark box so it can be read over the game
+fn draw_label(canvas: &mut Canvas, x: usize, y: usize, text: &str) {
+    canvas.fill_rect(x, y, text.chars().count() * CHAR_WIDTH + 2, GLYPH_HEIGHT + 2, BOX_COLOR);
+    canvas.draw_text(x + 1, y + 1, text, TEXT_COLOR);
+}
+
+// Draw an error banner along the bottom of the frame, wrapped to fit. Returns the top of the banner
+fn draw_error(canvas: &mut Canvas, text: &str) -> usize {
+    let columns = canvas.width() / CHAR_WIDTH - 2;
+    let chars: Vec<char> = text.chars().collect();
+    let lines: Vec<String> = chars.chunks(columns).map(|line| line.iter().collect()).collect();
+    let top = canvas.height() - (lines.len() + 1) * LINE_HEIGHT;
+    canvas.fill_rect(0, top, canvas.width(), canvas.height() - top, ERROR_COLOR);
+    for (i, line) in lines.iter().enumerate() {
+        canvas.draw_text(CHAR_WIDTH, top + LINE_HEIGHT / 2 + i * LINE_HEIGHT, line, TEXT_COLOR);
+    }
+    top
+}
@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::display::{self, Palette, HEIGHT, WIDTH};
+
+// Records presented frames and writes them as an animated GIF or APNG, picked from the file extension.
+// Frames are stamped with their 60Hz frame number so the output plays back at the emulated speed.
+pub struct FrameRecorder {
+    path: String,
+    palette: Palette,
+    scale: usize,
+    frames: Vec<(u64, Vec<bool>)>,
+}
+
+impl FrameRecorder {
+    pub fn new(path: &str, palette: Palette, scale: usize) -> Self {
+        FrameRecorder {
+            path: path.to_string(),
+            palette,
+            scale: scale.max(1),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Add the screen as presented on 60Hz frame `frame`
+    pub fn capture(&mut self, frame: u64, screen: &[bool]) {
+        match self.frames.last_mut() {
+            // Unchanged screens just extend the previous frame
+            Some((_, last)) if last.as_slice() == screen => (),
+            // Several presents within one frame, only the last one is seen
+            Some((last_frame, last)) if *last_frame >= frame => last.copy_from_slice(screen),
+            _ => self.frames.push((frame, screen.to_vec())),
+        }
+    }
+
+    // Write out the recording. `end_frame` is the 60Hz frame number the recording was stopped at
+    pub fn finish(self, end_frame: u64) -> Result<(), String> {
+        if self.frames.is_empty() {
+            return Err("nothing was recorded".to_string());
+        }
+        let is_png = Path::new(&self.path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png") || ext.eq_ignore_ascii_case("apng"));
+        let file = File::create(&self.path).map_err(|err| format!("unable to create {}: {}", self.path, err))?;
+        let writer = BufWriter::new(file);
+        if is_png {
+            self.write_apng(writer, end_frame).map_err(|err| err.to_string())
+        } else {
+            self.write_gif(writer, end_frame).map_err(|err| err.to_string())
+        }
+    }
+
+    // Number of 60Hz frames each recorded frame stays on screen
+    fn durations(&self, end_frame: u64) -> Vec<u64> {
+        let starts: Vec<u64> = self.frames.iter().map(|(frame, _)| *frame).collect();
+        let end = end_frame.max(starts[starts.len() - 1] + 1);
+        starts
+            .iter()
+            .zip(starts.iter().skip(1).chain(std::iter::once(&end)))
+            .map(|(start, next)| next - start)
+            .collect()
+    }
+
+    fn write_gif(&self, writer: BufWriter<File>, end_frame: u64) -> Result<(), gif::EncodingError> {
+        let width = (WIDTH * self.scale) as u16;
+        let height = (HEIGHT * self.scale) as u16;
+        let palette = [
+            self.palette.off[0], self.palette.off[1], self.palette.off[2],
+            self.palette.on[0], self.palette.on[1], self.palette.on[2],
+        ];
+        let mut encoder = gif::Encoder::new(writer, width, height, &palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        // GIF delays are in 1/100 s, so round the frame boundaries instead of each delay to avoid drift
+        let mut elapsed = 0;
+        for ((_, screen), duration) in self.frames.iter().zip(self.durations(end_frame)) {
+            let start_cs = elapsed * 100 / 60;
+            elapsed += duration;
+            let end_cs = elapsed * 100 / 60;
+
+            let frame = gif::Frame {
+                width,
+                height,
+                delay: (end_cs - start_cs).clamp(1, u16::MAX as u64) as u16,
+                buffer: display::to_indexed(screen, self.scale).into(),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn write_apng(&self, writer: BufWriter<File>, end_frame: u64) -> Result<(), png::EncodingError> {
+        let width = (WIDTH * self.scale) as u32;
+        let height = (HEIGHT * self.scale) as u32;
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(vec![
+            self.palette.off[0], self.palette.off[1], self.palette.off[2],
+            self.palette.on[0], self.palette.on[1], self.palette.on[2],
+        ]);
+        encoder.set_animated(self.frames.len() as u32, 0)?;
+
+        let mut writer = encoder.write_header()?;
+        for ((_, screen), duration) in self.frames.iter().zip(self.durations(end_frame)) {
+            writer.set_frame_delay(duration.min(u16::MAX as u64) as u16, 60)?;
+            writer.write_image_data(&display::to_indexed(screen, self.scale))?;
+        }
+        writer.finish()
+    }
+}
@@ -0,0 +1,447 @@
+// JSON-RPC 2.0 server for driving the emulator from other processes, such as bots and test tools.
+// Reference: https://www.jsonrpc.org/specification
+//
+// Requests and responses are one JSON object per line. The machine only runs when a request asks it to,
+// so callers get the same results every time for the same requests and seed. Clients are served one at
+// a time and share the machine.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::chip8::{Snapshot, DEFAULT_TICKRATE};
+use crate::cli::ServeArgs;
+use crate::config::Config;
+use crate::display::{self, WIDTH};
+use crate::env::{Env, EnvSpec};
+use crate::loader::{self, Overrides, RomSettings};
+
+// Error codes from the specification, and one for requests the machine refused
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const MACHINE_ERROR: i64 = -32000;
+
+// Most instructions or frames one request may run, so a typo can't hang the server
+const MAX_STEPS: u64 = 1_000_000;
+
+// The machine lives in the environment, which only answers the env_* methods when --env gave it a spec
+struct Server {
+    env: Env,
+    has_spec: bool,
+    config: Config,
+    overrides: Overrides,
+    settings: Option<RomSettings>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl ToString) -> Self {
+        RpcError { code: INVALID_PARAMS, message: message.to_string() }
+    }
+
+    fn machine(message: impl ToString) -> Self {
+        RpcError { code: MACHINE_ERROR, message: message.to_string() }
+    }
+}
+
+// Listen on localhost or a Unix socket, loading the ROM from the command line if there is one
+pub fn run(args: ServeArgs) -> Result<(), String> {
+    let machine = &args.machine;
+    let config = machine.load_config()?;
+    let spec = args.env.as_deref().map(|path| load_spec(Path::new(path))).transpose()?;
+    let mut server = Server {
+        has_spec: spec.is_some(),
+        env: Env::new(loader::new_machine(machine)?, spec.unwrap_or_default(), DEFAULT_TICKRATE),
+        config,
+        overrides: machine.overrides(),
+        settings: None,
+    };
+    if let Some(rom) = &machine.rom {
+        server.load(rom).map_err(|err| err.message)?;
+    }
+
+    match &args.socket {
+        Some(path) => serve_unix(path, &mut server),
+        None => {
+            let listener = TcpListener::bind(("127.0.0.1", args.port))
+                .map_err(|err| format!("unable to listen on port {}: {}", args.port, err))?;
+            println!("Serving JSON-RPC on 127.0.0.1:{}", args.port);
+            serve_connections(listener.incoming(), TcpStream::try_clone, &mut server);
+            Ok(())
+        }
+    }
+}
+
+// Serve each client until it disconnects, then wait for the next
+fn serve_connections<S: Read + Write>(
+    incoming: impl Iterator<Item = io::Result<S>>,
+    try_clone: fn(&S) -> io::Result<S>,
+    server: &mut Server,
+) {
+    for stream in incoming {
+        let result = stream.and_then(|stream| {
+            let reader = BufReader::new(try_clone(&stream)?);
+            server.serve_client(reader, stream)
+        });
+        if let Err(err) = result {
+            eprintln!("JSON-RPC client error: {}", err);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn serve_unix(path: &str, server: &mut Server) -> Result<(), String> {
+    use std::fs;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    // A socket left behind by an earlier server would stop the bind, anything else is left alone
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path).map_err(|err| format!("unable to remove the old socket {}: {}", path, err))?;
+    }
+    let listener = UnixListener::bind(path).map_err(|err| format!("unable to listen on {}: {}", path, err))?;
+    println!("Serving JSON-RPC on {}", path);
+    serve_connections(listener.incoming(), UnixStream::try_clone, server);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_path: &str, _server: &mut Server) -> Result<(), String> {
+    Err(String::from("Unix sockets aren't supported on this platform, use --port"))
+}
+
+impl Server {
+    fn serve_client(&mut self, reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_line(&line) {
+                writeln!(writer, "{}", response)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Answer one line, which holds a request or a batch of them. Notifications get no response
+    fn handle_line(&mut self, line: &str) -> Option<Value> {
+        match serde_json::from_str::<Value>(line) {
+            Ok(Value::Array(batch)) if !batch.is_empty() => {
+                let responses: Vec<Value> = batch.into_iter().filter_map(|request| self.handle_request(request)).collect();
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            Ok(Value::Array(_)) => Some(error_response(Value::Null, INVALID_REQUEST, "empty batch")),
+            Ok(request) => self.handle_request(request),
+            Err(err) => Some(error_response(Value::Null, PARSE_ERROR, &err.to_string())),
+        }
+    }
+
+    fn handle_request(&mut self, request: Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = request.get("method").and_then(Value::as_str);
+        let (Some(method), Some("2.0")) = (method, request.get("jsonrpc").and_then(Value::as_str)) else {
+            return Some(error_response(id.unwrap_or(Value::Null), INVALID_REQUEST, "expected a JSON-RPC 2.0 request"));
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let result = self.call(method, params);
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => error_response(id, err.code, &err.message),
+        })
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "load" => {
+                let LoadParams { path } = parse_params(params)?;
+                self.load(&path)
+            }
+            "reset" => {
+                self.env.chip8.reset();
+                Ok(Value::Null)
+            }
+            "step" => {
+                let StepParams { count } = parse_params(params)?;
+                if self.env.chip8.has_vip() {
+                    return Err(RpcError::machine("single instructions can't be stepped on the VIP, use run_frames"));
+                }
+                for _ in 0..check_count(count)? {
+                    self.env.chip8.tick();
+                }
+                Ok(json!({ "pc": self.env.chip8.pc }))
+            }
+            "run_frames" => {
+                let StepParams { count } = parse_params(params)?;
+                let tickrate = self.settings.as_ref().map_or(DEFAULT_TICKRATE, |settings| settings.tickrate);
+                for _ in 0..check_count(count)? {
+                    self.env.chip8.step_frame(tickrate);
+                }
+                Ok(json!({ "pc": self.env.chip8.pc, "frames": self.env.chip8.frames }))
+            }
+            "set_key" => {
+                let KeyParams { key, pressed } = parse_params(params)?;
+                let slot = self.env.chip8.key.get_mut(key as usize).ok_or_else(|| RpcError::invalid_params("key must be 0-15"))?;
+                *slot = pressed;
+                Ok(Value::Null)
+            }
+            "set_keys" => {
+                let KeysParams { pressed } = parse_params(params)?;
+                if pressed.iter().any(|&key| key > 0xF) {
+                    return Err(RpcError::invalid_params("keys must be 0-15"));
+                }
+                for (key, state) in self.env.chip8.key.iter_mut().enumerate() {
+                    *state = pressed.contains(&(key as u8));
+                }
+                Ok(Value::Null)
+            }
+            "get_screen" => {
+                let rows = display::to_text(&self.env.chip8.screen);
+                Ok(json!({ "width": WIDTH, "height": rows.len(), "rows": rows }))
+            }
+            "get_registers" => {
+                let chip8 = &mut self.env.chip8;
+                Ok(json!({
+                    "pc": chip8.pc,
+                    "i": chip8.i_register,
+                    "sp": chip8.stack_ptr,
+                    "v": chip8.v_register,
+                    "stack": chip8.stack[..chip8.stack_ptr as usize],
+                    "delay_timer": chip8.timer.get_dt(),
+                    "sound_timer": chip8.timer.get_st(),
+                }))
+            }
+            "read_memory" => {
+                let MemoryParams { address, length } = parse_params(params)?;
+                let range = self.memory_range(address, length)?;
+                Ok(json!({ "data": to_hex(&self.env.chip8.memory[range]) }))
+            }
+            "write_memory" => {
+                let WriteParams { address, data } = parse_params(params)?;
+                let data = from_hex(&data).ok_or_else(|| RpcError::invalid_params("data must be hex bytes"))?;
+                let range = self.memory_range(address, data.len())?;
+                for (address, &value) in range.zip(&data) {
+                    self.env.chip8.poke(address as u16, value);
+                }
+                Ok(Value::Null)
+            }
+            "save_state" => {
+                let snapshot = self.env.chip8.snapshot().map_err(RpcError::machine)?;
+                serde_json::to_value(snapshot).map_err(RpcError::machine)
+            }
+            "restore_state" => {
+                let RestoreParams { state } = parse_params(params)?;
+                // restore checks the registers, so a bad snapshot can't crash the next step
+                self.env.chip8.restore(&state).map_err(RpcError::invalid_params)?;
+                Ok(Value::Null)
+            }
+            "env_info" => {
+                let env = self.spec_env()?;
+                Ok(json!({ "actions": env.spec().action_count(), "frame_skip": env.spec().frame_skip }))
+            }
+            "env_reset" => {
+                let EnvResetParams { seed } = parse_params(params)?;
+                if self.settings.is_none() {
+                    return Err(RpcError::machine("no ROM is loaded"));
+                }
+                let env = self.spec_env()?;
+                let observation = env.reset(seed);
+                Ok(json!({ "observation": to_hex(&observation) }))
+            }
+            "env_step" => {
+                let EnvStepParams { action } = parse_params(params)?;
+                let env = self.spec_env()?;
+                let step = env.step(action).map_err(RpcError::machine)?;
+                Ok(json!({ "observation": to_hex(&step.observation), "reward": step.reward, "done": step.done }))
+            }
+            "env_render" => {
+                let env = self.spec_env()?;
+                Ok(json!({ "rows": env.render() }))
+            }
+            _ => Err(RpcError { code: METHOD_NOT_FOUND, message: format!("unknown method '{}'", method) }),
+        }
+    }
+
+    // Start a ROM, saving the RPL flags of the one it replaces
+    fn load(&mut self, path: &str) -> Result<Value, RpcError> {
+        if let Some(settings) = &self.settings {
+            self.config.save_rpl_flags(&settings.sha1, &self.env.chip8.rpl_flags).map_err(RpcError::machine)?;
+        }
+        let settings = loader::open_rom(&mut self.env.chip8, Path::new(path), &self.overrides, &self.config).map_err(RpcError::machine)?;
+        let result = json!({ "title": settings.title, "sha1": settings.sha1, "tickrate": settings.tickrate });
+        self.env.set_tickrate(settings.tickrate);
+        self.settings = Some(settings);
+        Ok(result)
+    }
+
+    fn spec_env(&mut self) -> Result<&mut Env, RpcError> {
+        if !self.has_spec {
+            return Err(RpcError::machine("no environment, start the server with --env"));
+        }
+        Ok(&mut self.env)
+    }
+
+    fn memory_range(&self, address: u16, length: usize) -> Result<std::ops::Range<usize>, RpcError> {
+        let start = address as usize;
+        let end = start.checked_add(length).filter(|&end| end <= self.env.chip8.memory.len());
+        let Some(end) = end else {
+            return Err(RpcError::invalid_params(format!("{:#05x}+{} is outside the {} bytes of memory", start, length, self.env.chip8.memory.len())));
+        };
+        Ok(start..end)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoadParams {
+    path: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepParams {
+    #[serde(default = "one")]
+    count: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyParams {
+    key: u8,
+    pressed: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeysParams {
+    pressed: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MemoryParams {
+    address: u16,
+    length: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WriteParams {
+    address: u16,
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RestoreParams {
+    state: Snapshot,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvResetParams {
+    seed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvStepParams {
+    action: usize,
+}
+
+fn one() -> u64 {
+    1
+}
+
+// Missing params are the same as an empty object, so defaults apply
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+// Read a reinforcement learning spec from a TOML file, see env.rs
+fn load_spec(path: &Path) -> Result<EnvSpec, String> {
+    let spec: EnvSpec = fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| toml::from_str(&text).map_err(|err| err.to_string()))
+        .map_err(|err| format!("unable to load {}: {}", path.display(), err))?;
+    spec.check().map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(spec)
+}
+
+fn check_count(count: u64) -> Result<u64, RpcError> {
+    if count > MAX_STEPS {
+        return Err(RpcError::invalid_params(format!("count must be at most {}", MAX_STEPS)));
+    }
+    Ok(count)
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    fn server() -> Server {
+        let env = Env::new(Chip8::new(), EnvSpec::default(), DEFAULT_TICKRATE);
+        Server { env, has_spec: false, config: Config::default(), overrides: Overrides::default(), settings: None }
+    }
+
+    fn call(server: &mut Server, method: &str, params: Value) -> Result<Value, i64> {
+        server.call(method, params).map_err(|err| err.code)
+    }
+
+    #[test]
+    fn memory_ranges_are_checked() {
+        let mut server = server();
+        assert_eq!(call(&mut server, "read_memory", json!({ "address": 0xFFE, "length": 2 })), Ok(json!({ "data": "0000" })));
+        assert_eq!(call(&mut server, "read_memory", json!({ "address": 0xFFE, "length": 3 })), Err(INVALID_PARAMS));
+        assert_eq!(call(&mut server, "read_memory", json!({ "address": 1, "length": usize::MAX })), Err(INVALID_PARAMS));
+    }
+
+    #[test]
+    fn writes_are_recorded() {
+        let mut server = server();
+        server.env.chip8.take_writes();
+        assert_eq!(call(&mut server, "write_memory", json!({ "address": 0x300, "data": "abcd" })), Ok(Value::Null));
+        assert_eq!(server.env.chip8.memory[0x300..0x302], [0xAB, 0xCD]);
+        let written = server.env.chip8.take_writes();
+        assert!(written[0x300] && written[0x301] && !written[0x302]);
+    }
+
+    #[test]
+    fn bad_snapshots_are_refused() {
+        let mut server = server();
+        let mut state = call(&mut server, "save_state", Value::Null).unwrap();
+        state["pc"] = json!(0xFFF);
+        assert_eq!(call(&mut server, "restore_state", json!({ "state": state })), Err(INVALID_PARAMS));
+        assert_eq!(server.env.chip8.pc, 0x200);
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::display::{self, Palette, HEIGHT, WIDTH};
+
+#[derive(Clone, Copy, Debug)]
+pub enum ScreenshotFormat {
+    // RGBA image in the given palette, every pixel scaled up to a scale x scale block
+    Color { palette: Palette, scale: usize },
+    // 64x32 1-bit grayscale image, white for lit pixels
+    Raw,
+}
+
+// Save the screen to a timestamped PNG in the current directory, returning the file name
+pub fn save_screenshot(screen: &[bool], format: ScreenshotFormat) -> Result<String, String> {
+    let path = timestamped_path("screenshot", "png");
+    save_png(screen, &path, format)?;
+    Ok(path)
+}
+
+// Save the screen as a PNG at `path`
+pub fn save_png(screen: &[bool], path: &str, format: ScreenshotFormat) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| format!("unable to create {}: {}", path, err))?;
+    let (width, height, color, depth, data) = match format {
+        ScreenshotFormat::Color { palette, scale } => {
+            let scale = scale.max(1);
+            let data = display::to_rgba(screen, &palette, scale);
+            (WIDTH * scale, HEIGHT * scale, png::ColorType::Rgba, png::BitDepth::Eight, data)
+        }
+        ScreenshotFormat::Raw => {
+            // 1-bit grayscale rows are packed like the VIP's display buffer
+            let data = display::to_bits(screen);
+            (WIDTH, HEIGHT, png::ColorType::Grayscale, png::BitDepth::One, data)
+        }
+    };
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer.write_image_data(&data).map_err(|err| err.to_string())
+}
+
+// Build a file name like `screenshot-1666000000123.png` from the current time in milliseconds
+pub fn timestamped_path(prefix: &str, extension: &str) -> String {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    format!("{}-{}.{}", prefix, timestamp, extension)
+}
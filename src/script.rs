@@ -0,0 +1,371 @@
+// Rhai scripts with hooks into the running machine, for test scenarios, bots and HUDs without recompiling.
+// The top level of the script runs once when it is loaded and registers the hooks:
+//
+//     on_frame(|| hud(`score ${peek(0x2F0)}`));    // after every frame
+//     on_exec(0x228, |pc| press(5));              // before the instruction at 0x228 runs
+//     on_write(0x2F3, |address, value| if value == 0 { print("game over"); quit(1) });
+//
+// Hooks see the machine through functions: pc(), i(), sp(), v(x), dt(), st(), frame(), peek(address) and
+// is_pressed(key) read it, set_pc, set_i, set_v, set_dt, set_st, poke, press and release change it, hud(text)
+// shows text over the game, pause() pauses the emulator and quit(code) asks the frontend to exit with that
+// status. A hook that fails, or runs for too long, stops the script and shows the error.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, FuncArgs, AST};
+
+use crate::chip8::{Chip8, Hooks};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// Most operations the top level or one round of hooks may run, and how long they may take, so a script
+// stuck in a loop stops with an error instead of hanging the emulator
+const MAX_OPERATIONS: u64 = 10_000_000;
+const TIME_LIMIT: Duration = Duration::from_secs(1);
+
+// The machine as the hooks see it. It is copied in before hooks run and written back afterwards. Pokes are
+// also queued, so they reach memory through `Chip8::poke` and are recorded like the program's own writes
+struct Machine {
+    memory: Vec<u8>,
+    pokes: Vec<(u16, u8)>,
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    sp: u16,
+    dt: u8,
+    st: u8,
+    keys: [bool; 16],
+    frames: u64,
+}
+
+impl Machine {
+    fn load(chip8: &mut Chip8) -> Self {
+        Machine {
+            memory: chip8.memory.to_vec(),
+            pokes: Vec::new(),
+            v: chip8.v_register,
+            i: chip8.i_register,
+            pc: chip8.pc,
+            sp: chip8.stack_ptr,
+            dt: chip8.timer.get_dt(),
+            st: chip8.timer.get_st(),
+            keys: chip8.key,
+            frames: chip8.frames,
+        }
+    }
+
+    fn store(self, chip8: &mut Chip8) {
+        for (address, value) in self.pokes {
+            chip8.poke(address, value);
+        }
+        chip8.v_register = self.v;
+        chip8.i_register = self.i;
+        chip8.pc = self.pc;
+        chip8.timer.set_dt(self.dt);
+        chip8.timer.set_st(self.st);
+        chip8.key = self.keys;
+    }
+}
+
+// Shared between the script functions and the hooks
+#[derive(Default)]
+struct State {
+    machine: Option<Machine>,
+    frame_hooks: Vec<FnPtr>,
+    exec_hooks: BTreeMap<u16, Vec<FnPtr>>,
+    write_hooks: BTreeMap<u16, Vec<FnPtr>>,
+    hud: Vec<String>,
+    pause: bool,
+    quit: Option<i32>,
+    deadline: Option<Instant>,
+}
+
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    state: Arc<Mutex<State>>,
+    error: Option<String>,
+}
+
+impl Script {
+    // Compile the script and run its top level, which registers the hooks
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let state = Arc::new(Mutex::new(State::default()));
+        let engine = engine(&state);
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|err| format!("unable to load {}: {}", path.display(), err))?;
+        state.lock().unwrap().deadline = Some(Instant::now() + TIME_LIMIT);
+        engine.run_ast(&ast).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Script { engine, ast, state, error: None })
+    }
+
+    // Start calling the script's hooks from the machine
+    pub fn attach(self, chip8: &mut Chip8) {
+        self.sync_addresses(chip8);
+        chip8.set_hooks(Box::new(self));
+    }
+
+    // Tell the machine which addresses have hooks
+    fn sync_addresses(&self, chip8: &mut Chip8) {
+        let state = self.state.lock().unwrap();
+        chip8.exec_hooks = state.exec_hooks.keys().copied().collect();
+        chip8.write_hooks = state.write_hooks.keys().copied().collect();
+    }
+
+    fn call(&mut self, chip8: &mut Chip8, hooks: Vec<FnPtr>, args: impl FuncArgs + Clone) {
+        if self.error.is_some() || hooks.is_empty() {
+            return;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            state.machine = Some(Machine::load(chip8));
+            state.deadline = Some(Instant::now() + TIME_LIMIT);
+        }
+        let result = hooks.iter().try_for_each(|hook| hook.call::<Dynamic>(&self.engine, &self.ast, args.clone()).map(drop));
+        let mut state = self.state.lock().unwrap();
+        if let Some(machine) = state.machine.take() {
+            machine.store(chip8);
+        }
+        if std::mem::take(&mut state.pause) || state.quit.is_some() {
+            chip8.pause();
+        }
+        drop(state);
+        if let Err(err) = result {
+            let error = format!("script error: {}", err);
+            eprintln!("{}", error);
+            self.error = Some(error);
+            chip8.exec_hooks.clear();
+            chip8.write_hooks.clear();
+            return;
+        }
+        // Hooks can add more hooks
+        self.sync_addresses(chip8);
+    }
+}
+
+impl Hooks for Script {
+    fn frame(&mut self, chip8: &mut Chip8) {
+        let hooks = self.state.lock().unwrap().frame_hooks.clone();
+        self.call(chip8, hooks, ());
+    }
+
+    fn exec(&mut self, chip8: &mut Chip8, address: u16) {
+        let hooks = self.state.lock().unwrap().exec_hooks.get(&address).cloned().unwrap_or_default();
+        self.call(chip8, hooks, (address as i64,));
+    }
+
+    fn write(&mut self, chip8: &mut Chip8, address: u16, value: u8) {
+        let hooks = self.state.lock().unwrap().write_hooks.get(&address).cloned().unwrap_or_default();
+        self.call(chip8, hooks, (address as i64, value as i64));
+    }
+
+    fn overlay(&self) -> Vec<String> {
+        match &self.error {
+            Some(error) => vec![error.clone()],
+            None => self.state.lock().unwrap().hud.clone(),
+        }
+    }
+
+    fn quit(&self) -> Option<i32> {
+        self.state.lock().unwrap().quit
+    }
+}
+
+// Run `f` on the copy of the machine the hooks were called for
+fn with_machine<T>(state: &Mutex<State>, f: impl FnOnce(&mut Machine) -> T) -> ScriptResult<T> {
+    match state.lock().unwrap().machine.as_mut() {
+        Some(machine) => Ok(f(machine)),
+        None => Err("the machine can only be used from hooks".into()),
+    }
+}
+
+fn address(address: i64) -> ScriptResult<u16> {
+    match u16::try_from(address) {
+        Ok(address) if (address as usize) < 4096 => Ok(address),
+        _ => Err(format!("address {} is outside memory", address).into()),
+    }
+}
+
+fn byte(value: i64) -> ScriptResult<u8> {
+    u8::try_from(value).map_err(|_| format!("{} doesn't fit in a byte", value).into())
+}
+
+fn index(index: i64, what: &str) -> ScriptResult<usize> {
+    match usize::try_from(index) {
+        Ok(index) if index < 16 => Ok(index),
+        _ => Err(format!("{} must be 0-15, not {}", what, index).into()),
+    }
+}
+
+// An engine with the machine functions, working on `state`
+fn engine(state: &Arc<Mutex<State>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let s = Arc::clone(state);
+    engine.on_progress(move |operations| {
+        // Checking the clock on every operation would slow scripts down
+        if operations % 10_000 != 0 {
+            return None;
+        }
+        let deadline = s.lock().unwrap().deadline;
+        deadline.filter(|&deadline| Instant::now() > deadline).map(|_| Dynamic::from(format!("script ran for more than {:?}", TIME_LIMIT)))
+    });
+
+    let s = Arc::clone(state);
+    engine.register_fn("on_frame", move |hook: FnPtr| s.lock().unwrap().frame_hooks.push(hook));
+    let s = Arc::clone(state);
+    engine.register_fn("on_exec", move |at: i64, hook: FnPtr| -> ScriptResult<()> {
+        s.lock().unwrap().exec_hooks.entry(address(at)?).or_default().push(hook);
+        Ok(())
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("on_write", move |at: i64, hook: FnPtr| -> ScriptResult<()> {
+        s.lock().unwrap().write_hooks.entry(address(at)?).or_default().push(hook);
+        Ok(())
+    });
+
+    let s = Arc::clone(state);
+    engine.register_fn("pc", move || with_machine(&s, |machine| machine.pc as i64));
+    let s = Arc::clone(state);
+    engine.register_fn("set_pc", move |value: i64| -> ScriptResult<()> {
+        let value = address(value)?;
+        with_machine(&s, |machine| machine.pc = value)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("i", move || with_machine(&s, |machine| machine.i as i64));
+    let s = Arc::clone(state);
+    engine.register_fn("set_i", move |value: i64| -> ScriptResult<()> {
+        let value = u16::try_from(value).map_err(|_| format!("{} doesn't fit in I", value))?;
+        with_machine(&s, |machine| machine.i = value)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("sp", move || with_machine(&s, |machine| machine.sp as i64));
+    let s = Arc::clone(state);
+    engine.register_fn("v", move |x: i64| -> ScriptResult<i64> {
+        let x = index(x, "register")?;
+        with_machine(&s, |machine| machine.v[x] as i64)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("set_v", move |x: i64, value: i64| -> ScriptResult<()> {
+        let (x, value) = (index(x, "register")?, byte(value)?);
+        with_machine(&s, |machine| machine.v[x] = value)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("dt", move || with_machine(&s, |machine| machine.dt as i64));
+    let s = Arc::clone(state);
+    engine.register_fn("set_dt", move |value: i64| -> ScriptResult<()> {
+        let value = byte(value)?;
+        with_machine(&s, |machine| machine.dt = value)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("st", move || with_machine(&s, |machine| machine.st as i64));
+    let s = Arc::clone(state);
+    engine.register_fn("set_st", move |value: i64| -> ScriptResult<()> {
+        let value = byte(value)?;
+        with_machine(&s, |machine| machine.st = value)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("frame", move || with_machine(&s, |machine| machine.frames as i64));
+
+    let s = Arc::clone(state);
+    engine.register_fn("peek", move |at: i64| -> ScriptResult<i64> {
+        let at = address(at)?;
+        with_machine(&s, |machine| machine.memory[at as usize] as i64)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("poke", move |at: i64, value: i64| -> ScriptResult<()> {
+        let (at, value) = (address(at)?, byte(value)?);
+        with_machine(&s, |machine| {
+            machine.memory[at as usize] = value;
+            machine.pokes.push((at, value));
+        })
+    });
+
+    let s = Arc::clone(state);
+    engine.register_fn("press", move |key: i64| -> ScriptResult<()> {
+        let key = index(key, "key")?;
+        with_machine(&s, |machine| machine.keys[key] = true)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("release", move |key: i64| -> ScriptResult<()> {
+        let key = index(key, "key")?;
+        with_machine(&s, |machine| machine.keys[key] = false)
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("is_pressed", move |key: i64| -> ScriptResult<bool> {
+        let key = index(key, "key")?;
+        with_machine(&s, |machine| machine.keys[key])
+    });
+
+    let s = Arc::clone(state);
+    engine.register_fn("hud", move |text: &str| {
+        s.lock().unwrap().hud = text.lines().map(String::from).collect();
+    });
+    let s = Arc::clone(state);
+    engine.register_fn("pause", move || s.lock().unwrap().pause = true);
+    // The frontend checks for this after each frame and exits the same way as when the window is closed
+    let s = Arc::clone(state);
+    engine.register_fn("quit", move |code: i64| -> ScriptResult<()> {
+        let code = i32::try_from(code).map_err(|_| format!("exit status {} is out of range", code))?;
+        s.lock().unwrap().quit = Some(code);
+        Ok(())
+    });
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::Timing;
+
+    // A machine looping at 0x200 with the script attached
+    fn machine(name: &str, source: &str) -> Result<Chip8, String> {
+        let path = std::env::temp_dir().join(format!("rust-chip8-{}-{}.rhai", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let script = Script::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let mut chip8 = Chip8::new();
+        chip8.load(&[0x12, 0x00]).unwrap();
+        chip8.timing = Timing::Fixed;
+        script?.attach(&mut chip8);
+        Ok(chip8)
+    }
+
+    #[test]
+    fn hooks_change_the_machine() {
+        let mut chip8 = machine("change", "on_exec(0x200, |pc| { poke(0x300, peek(0x300) + 1); set_v(1, 7); press(pc - 0x200 + 5) });").unwrap();
+        chip8.take_writes();
+        for _ in 0..3 {
+            chip8.tick();
+        }
+        assert_eq!((chip8.memory[0x300], chip8.v_register[1], chip8.key[5]), (3, 7, true));
+        assert!(chip8.take_writes()[0x300]);
+    }
+
+    #[test]
+    fn quit_asks_the_frontend() {
+        let mut chip8 = machine("quit", "on_frame(|| quit(3));").unwrap();
+        assert_eq!(chip8.quit_requested(), None);
+        chip8.run_frame(10);
+        assert_eq!(chip8.quit_requested(), Some(3));
+        assert!(chip8.is_paused());
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let mut chip8 = machine("runaway", "on_frame(|| { loop {} });").unwrap();
+        chip8.run_frame(10);
+        assert!(chip8.overlay()[0].starts_with("script error"));
+        assert!(machine("top-level", "loop {}").is_err());
+    }
+
+    #[test]
+    fn the_machine_is_only_there_in_hooks() {
+        assert!(machine("outside", "peek(0x200);").is_err());
+    }
+}
@@ -1,46 +1,50 @@
-use std::sync::Arc;
-use std::sync::atomic::{AtomicU8, Ordering};
-use std::thread;
-use std::time::Duration;
+// Called on every 60Hz tick with the sound timer value before it is decremented
+type TickHook = Box<dyn FnMut(u8) + Send>;
 
+// Delay and sound timers. They count down at 60Hz of emulated time, driven by `Chip8::run_frame`,
+// so they stop while the emulator is paused and keep in step with frame advance
 pub struct Timer {
-    dt: Arc<AtomicU8>,
-    st: Arc<AtomicU8>,
+    dt: u8,
+    st: u8,
+    on_tick: Option<TickHook>,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
 }
 
 impl Timer {
     pub fn new() -> Self {
         Timer {
-            dt: Arc::new(AtomicU8::new(0)),
-            st: Arc::new(AtomicU8::new(0)),
+            dt: 0,
+            st: 0,
+            on_tick: None,
         }
     }
-    pub fn start(&mut self) {
-        let dt = Arc::clone(&self.dt);
-        let st = Arc::clone(&self.st);
-
-        thread::spawn(move || loop {
-            spin_sleep::sleep(Duration::new(0, 16666666));
-
-            let dt_curr = dt.load(Ordering::Relaxed);
-            let st_curr = st.load(Ordering::Relaxed);
+    // Count both timers down by one
+    pub fn tick(&mut self) {
+        if let Some(hook) = self.on_tick.as_mut() {
+            hook(self.st);
+        }
 
-            if dt_curr > 0 { dt.store(dt_curr - 1, Ordering::Relaxed); }
-            if st_curr > 0 { st.store(st_curr - 1, Ordering::Relaxed); }
-        });
+        if self.dt > 0 { self.dt -= 1; }
+        if self.st > 0 { self.st -= 1; }
+    }
+    pub fn set_tick_hook(&mut self, hook: impl FnMut(u8) + Send + 'static) {
+        self.on_tick = Some(Box::new(hook));
     }
     pub fn set_dt(&mut self, dt_value: u8) {
-        let dt = Arc::clone(&self.dt);
-        dt.store(dt_value, Ordering::Relaxed);
+        self.dt = dt_value;
     }
     pub fn set_st(&mut self, st_value: u8) {
-        let st = Arc::clone(&self.st);
-        st.store(st_value, Ordering::Relaxed);
+        self.st = st_value;
     }
     pub fn get_dt(&mut self) -> u8 {
-        self.dt.load(Ordering::Relaxed)
+        self.dt
     }
     pub fn get_st(&mut self) -> u8 {
-        self.st.load(Ordering::Relaxed)
+        self.st
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,125 @@
+// How many instructions run in a frame.
+// Fixed timing runs the same number of instructions every frame. VIP timing charges every instruction
+// what it cost the original COSMAC VIP interpreter and gives each frame the cycles the VIP had.
+//
+// Costs are in 1802 machine cycles (8 clocks of the 3.52128/2 MHz clock, about 4.54us) and follow Laurence
+// Scotford's disassembly and timing analysis of the VIP interpreter ("Chip-8 on the COSMAC VIP",
+// laurencescotford.net), which counts the cycles of each instruction's routine: 40 for the fetch and
+// decode loop, then the routine itself, with the data dependent ones (sprites, BCD, register loads and
+// stores) worked out per row, digit or register.
+
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+    #[default]
+    Fixed,
+    Vip,
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fixed" => Ok(Timing::Fixed),
+            "vip" => Ok(Timing::Vip),
+            _ => Err(format!("unknown timing '{}', expected fixed or vip", s)),
+        }
+    }
+}
+
+// Machine cycles per 60Hz frame: 1,760,640 Hz / 8 clocks / 60
+const CYCLES_PER_FRAME: i64 = 3668;
+// Cycles the CDP1861 takes every frame for DMA: 128 lines of 8 bytes
+const DISPLAY_DMA_CYCLES: i64 = 1024;
+// The interrupt routine that starts the DMA and counts the timers down
+const INTERRUPT_CYCLES: i64 = 46;
+// Cycles left for the interpreter in each frame
+pub const VIP_FRAME_CYCLES: i64 = CYCLES_PER_FRAME - DISPLAY_DMA_CYCLES - INTERRUPT_CYCLES;
+
+// Fetching and decoding, paid by every instruction
+const FETCH_CYCLES: u32 = 40;
+
+// Extra cost of a skip instruction when it skips
+const SKIP_CYCLES: u32 = 4;
+
+// Cost of an instruction on the VIP, including the fetch. `vx` and `vy` are the registers named in the
+// opcode, read before the instruction ran, `skipped` tells whether a skip instruction skipped and `wrap`
+// whether sprites wrap around the screen edges instead of being clipped
+pub fn vip_cycles(opcode: u16, vx: u8, vy: u8, skipped: bool, wrap: bool) -> u32 {
+    let x = (opcode & 0x0F00) >> 8;
+    let n = (opcode & 0x000F) as u32;
+    let skip = if skipped { SKIP_CYCLES } else { 0 };
+    let execute = match ((opcode & 0xF000) >> 12, opcode & 0x00FF) {
+        (0, 0xE0) => 1544,                    // Clears the 256 display bytes, 6 cycles a byte
+        (0, 0xEE) => 10,
+        (0, _) => 0,                          // Machine code routines aren't emulated
+        (1, _) => 12,
+        (2, _) => 26,
+        (3 | 4 | 5 | 9, _) => 10 + skip,
+        (6, _) => 6,
+        (7, _) => 10,
+        (8, _) => 44,                         // Builds the ALU instruction in RAM and calls it
+        (0xA, _) => 12,
+        (0xB, _) => 22,
+        (0xC, _) => 36,
+        (0xD, _) => sprite_cycles(vx, vy, n, wrap),
+        (0xE, _) => 14 + skip,
+        (0xF, 0x0A) => 16,                    // One poll of the keypad, repeated until a key is down
+        (0xF, 0x1E | 0x29) => 14,
+        (0xF, 0x33) => bcd_cycles(vx),
+        (0xF, 0x55 | 0x65) => 14 + 14 * (x as u32 + 1),
+        (0xF, _) => 10,
+        _ => 0,
+    };
+    FETCH_CYCLES + execute
+}
+
+// Sprites are drawn a row at a time. A row that isn't byte aligned is shifted into two bytes one bit at a
+// time, so it costs more the further it is from the byte boundary. Rows past the bottom edge are skipped
+// unless they wrap around to the top
+fn sprite_cycles(vx: u8, vy: u8, n: u32, wrap: bool) -> u32 {
+    let shift = (vx % 8) as u32;
+    let rows = if wrap { n } else { n.min(32 - (vy % 32) as u32) };
+    let row = if shift == 0 { 26 } else { 38 + 8 * shift };
+    68 + rows * row
+}
+
+// FX33 finds the digits by repeated subtraction, so bigger numbers take longer
+fn bcd_cycles(vx: u8) -> u32 {
+    let digits = (vx / 100 + vx / 10 % 10 + vx % 10) as u32;
+    40 + 8 * digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clear_screen() {
+        assert_eq!(vip_cycles(0x00E0, 0, 0, false, false), 40 + 1544);
+    }
+
+    #[test]
+    fn key_wait_costs_one_poll() {
+        assert_eq!(vip_cycles(0xF30A, 0, 0, false, false), 40 + 16);
+    }
+
+    #[test]
+    fn sprites() {
+        // 5 rows, byte aligned
+        assert_eq!(vip_cycles(0xD015, 8, 0, false, false), 40 + 68 + 5 * 26);
+        // 5 rows, 3 bits into the byte
+        assert_eq!(vip_cycles(0xD015, 11, 0, false, false), 40 + 68 + 5 * (38 + 24));
+        // Starting on row 30 only 2 of the 5 rows are drawn, unless they wrap to the top
+        assert_eq!(vip_cycles(0xD015, 8, 30, false, false), 40 + 68 + 2 * 26);
+        assert_eq!(vip_cycles(0xD015, 8, 30, false, true), 40 + 68 + 5 * 26);
+    }
+
+    #[test]
+    fn skips_cost_more_when_they_skip() {
+        assert_eq!(vip_cycles(0x3012, 0, 0, false, false), 40 + 10);
+        assert_eq!(vip_cycles(0x3012, 0, 0, true, false), 40 + 14);
+    }
+}
@@ -0,0 +1,128 @@
+// The beeper: an oscillator gated by the sound timer. The frontend plays it through the sound device and
+// the WAV recorder writes it to a file
+
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+// Shape of the beeper tone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    // Sample the waveform at phase in [0, 1), returning a value in [-1, 1]
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            _ => Err(format!("unknown waveform '{}', expected square, sine, triangle or sawtooth", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AudioConfig {
+    pub waveform: Waveform,
+    pub frequency: f32, // Hz
+    pub volume: f32,    // 0.0 - 1.0
+    pub attack: f32,    // seconds to fade in when the sound timer starts
+    pub release: f32,   // seconds to fade out when the sound timer stops
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+            attack: 0.005,
+            release: 0.005,
+        }
+    }
+}
+
+// Oscillator with a linear attack/release envelope, so gating it on and off doesn't click
+pub struct Tone {
+    config: AudioConfig,
+    sample_rate: f32,
+    phase: f32,
+    level: f32,
+}
+
+impl Tone {
+    pub fn new(config: AudioConfig, sample_rate: f32) -> Self {
+        Tone {
+            config,
+            sample_rate,
+            phase: 0.0,
+            level: 0.0,
+        }
+    }
+
+    // Produce the next sample. `gate` is whether the sound timer is currently active
+    pub fn next_sample(&mut self, gate: bool) -> f32 {
+        let (target, time) = if gate { (1.0, self.config.attack) } else { (0.0, self.config.release) };
+        let step = if time > 0.0 { 1.0 / (time * self.sample_rate) } else { 1.0 };
+        if self.level < target {
+            self.level = (self.level + step).min(target);
+        } else if self.level > target {
+            self.level = (self.level - step).max(target);
+        }
+
+        // Keep the oscillator running while silent so the phase stays continuous
+        let value = self.config.waveform.sample(self.phase);
+        self.phase = (self.phase + self.config.frequency / self.sample_rate) % 1.0;
+        value * self.level * self.config.volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveforms() {
+        let at = |waveform: Waveform| [0.0, 0.25, 0.5, 0.75].map(|phase| waveform.sample(phase));
+        assert_eq!(at(Waveform::Square), [1.0, 1.0, -1.0, -1.0]);
+        assert_eq!(at(Waveform::Triangle), [-1.0, 0.0, 1.0, 0.0]);
+        assert_eq!(at(Waveform::Sawtooth), [-1.0, -0.5, 0.0, 0.5]);
+        let sine = at(Waveform::Sine);
+        assert!(sine[0].abs() < 1e-6 && (sine[1] - 1.0).abs() < 1e-6 && (sine[3] + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn gating_ramps_instead_of_clicking() {
+        // A slow square wave stays high for the whole test, so samples follow the envelope. Attack and
+        // release take 10 samples each
+        let config = AudioConfig { waveform: Waveform::Square, frequency: 1.0, volume: 1.0, attack: 0.001, release: 0.001 };
+        let mut tone = Tone::new(config, 10_000.0);
+        let attack: Vec<f32> = (0..12).map(|_| tone.next_sample(true)).collect();
+        assert!((attack[0] - 0.1).abs() < 1e-4);
+        assert!(attack.windows(2).all(|pair| pair[1] >= pair[0] && pair[1] - pair[0] < 0.11));
+        assert!((attack[9] - 1.0).abs() < 1e-4 && attack[11] == 1.0);
+
+        let release: Vec<f32> = (0..12).map(|_| tone.next_sample(false)).collect();
+        assert!((release[0] - 0.9).abs() < 1e-4);
+        assert!(release.windows(2).all(|pair| pair[1] <= pair[0] && pair[0] - pair[1] < 0.11));
+        assert_eq!(release[11], 0.0);
+    }
+}
@@ -0,0 +1,274 @@
+// Low-level COSMAC VIP: a CDP1802 running the original CHIP-8 interpreter, with the CDP1861 video chip,
+// the hex keypad and the speaker. The machine's 4K of RAM is the `Chip8` memory, so the memory viewer and
+// the rest of the emulator see what the interpreter sees.
+//
+// Memory map: RAM at 0x0000-0x0FFF, mirrored up to 0x7FFF, and the 512 byte monitor ROM at 0x8000, mirrored
+// up to 0xFFFF. After a reset the ROM also appears at 0x0000 until the first access above 0x8000, which is how
+// the VIP starts the monitor. The monitor jumps to the interpreter at 0x0000 unless C is held down.
+
+use crate::cdp1802::{Bus, Cdp1802};
+use crate::display::{HEIGHT, WIDTH};
+
+const RAM_SIZE: usize = 4096;
+const MONITOR_SIZE: usize = 512;
+// The interpreter lives below the first CHIP-8 instruction
+const INTERPRETER_SIZE: usize = 0x200;
+
+// CDP1861 timing, in machine cycles and scan lines
+const LINE_CYCLES: u32 = 14;
+const FRAME_LINES: u32 = 262;
+const FRAME_CYCLES: u32 = LINE_CYCLES * FRAME_LINES;
+// Lines that show memory: 128 lines of 8 bytes, fetched by DMA
+const DISPLAY_START: u32 = 80;
+const DISPLAY_END: u32 = 208;
+const DISPLAY_LINES: usize = (DISPLAY_END - DISPLAY_START) as usize;
+const LINE_BYTES: usize = 8;
+// Where in a display line the DMA request goes up
+const DMA_START: u32 = 2;
+// The interrupt request is held for the two lines before the display starts, so the interrupt routine
+// can point R0 at the display buffer in time
+const INTERRUPT_START: u32 = 78;
+// EF1 is asserted for the four lines before the display starts and before it ends
+const EF1_TOP: u32 = 76;
+const EF1_BOTTOM: u32 = 204;
+
+// Everything the CPU is wired to
+struct Board<'a> {
+    ram: &'a mut [u8; RAM_SIZE],
+    written: &'a mut [bool; RAM_SIZE],
+    monitor: &'a [u8; MONITOR_SIZE],
+    keys: &'a [bool; 16],
+    devices: &'a mut Devices,
+}
+
+// State of the VIP outside the CPU and memory
+struct Devices {
+    rom_mirror: bool, // The monitor is also at 0x0000, until the first access above 0x8000
+    key_latch: u8,    // Keypad key selected by OUT 2, its state is read on EF3
+    display_on: bool, // Switched on by INP 1 and off by OUT 1
+    line: u32,        // Scan line being drawn
+}
+
+impl Bus for Board<'_> {
+    fn read(&mut self, address: u16) -> u8 {
+        if address >= 0x8000 {
+            self.devices.rom_mirror = false;
+        }
+        if address >= 0x8000 || self.devices.rom_mirror {
+            self.monitor[address as usize % MONITOR_SIZE]
+        } else {
+            self.ram[address as usize % RAM_SIZE]
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.devices.rom_mirror = false;
+            return;
+        }
+        let address = address as usize % RAM_SIZE;
+        self.ram[address] = value;
+        self.written[address] = true;
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.devices.display_on = false,
+            2 => self.devices.key_latch = value & 0x0F,
+            _ => (), // 4 drives the hex display of the optional expansion, not emulated
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.devices.display_on = true;
+        }
+        // Nothing drives the data bus, it reads as pulled up
+        0xFF
+    }
+
+    fn flag(&mut self, flag: u8) -> bool {
+        let line = self.devices.line;
+        match flag {
+            1 => (EF1_TOP..DISPLAY_START).contains(&line) || (EF1_BOTTOM..DISPLAY_END).contains(&line),
+            3 => self.keys[self.devices.key_latch as usize],
+            _ => false, // EF2 is the cassette input, EF4 the expansion port
+        }
+    }
+}
+
+pub struct Vip {
+    cpu: Cdp1802,
+    devices: Devices,
+    interpreter: Vec<u8>,
+    monitor: Box<[u8; MONITOR_SIZE]>,
+    cycle: u32,                                   // Machine cycle within the frame
+    frame: [u8; DISPLAY_LINES * LINE_BYTES],       // Bytes the CDP1861 showed this frame
+}
+
+impl Vip {
+    // Build a VIP from the CHIP-8 interpreter image, which is loaded at 0x0000, and the monitor ROM.
+    // The monitor is needed because the interpreter uses its display interrupt routine
+    pub fn new(interpreter: &[u8], monitor: &[u8]) -> Result<Self, String> {
+        if interpreter.len() > INTERPRETER_SIZE {
+            return Err(format!("VIP interpreter is too large ({} bytes, at most {} fit below 0x200)", interpreter.len(), INTERPRETER_SIZE));
+        }
+        if monitor.len() > MONITOR_SIZE {
+            return Err(format!("VIP monitor is too large ({} bytes, the ROM holds {})", monitor.len(), MONITOR_SIZE));
+        }
+        let mut rom = Box::new([0xFF; MONITOR_SIZE]);
+        rom[..monitor.len()].copy_from_slice(monitor);
+        Ok(Vip {
+            cpu: Cdp1802::new(),
+            devices: Devices { rom_mirror: true, key_latch: 0, display_on: false, line: 0 },
+            interpreter: interpreter.to_vec(),
+            monitor: rom,
+            cycle: 0,
+            frame: [0; DISPLAY_LINES * LINE_BYTES],
+        })
+    }
+
+    // Switch the machine on with the interpreter in otherwise empty RAM. The caller loads the ROM at 0x200
+    pub fn reset(&mut self, memory: &mut [u8; RAM_SIZE]) {
+        *memory = [0; RAM_SIZE];
+        memory[..self.interpreter.len()].copy_from_slice(&self.interpreter);
+        self.cpu = Cdp1802::new();
+        self.devices = Devices { rom_mirror: true, key_latch: 0, display_on: false, line: 0 };
+        self.cycle = 0;
+        self.frame = [0; DISPLAY_LINES * LINE_BYTES];
+    }
+
+    // Run one 60Hz frame of 262 scan lines. Returns the number of 1802 instructions executed
+    pub fn run_frame(&mut self, memory: &mut [u8; RAM_SIZE], written: &mut [bool; RAM_SIZE], keys: &[bool; 16]) -> u64 {
+        let mut board = Board { ram: memory, written, monitor: &self.monitor, keys, devices: &mut self.devices };
+        let mut instructions = 0;
+        let mut dma_line = None;
+        while self.cycle < FRAME_CYCLES {
+            let line = self.cycle / LINE_CYCLES;
+            board.devices.line = line;
+            let display = board.devices.display_on;
+
+            // DMA and interrupt requests are taken between instructions, DMA first
+            let in_display = (DISPLAY_START..DISPLAY_END).contains(&line);
+            if display && in_display && dma_line != Some(line) && self.cycle % LINE_CYCLES >= DMA_START {
+                let start = (line - DISPLAY_START) as usize * LINE_BYTES;
+                for byte in &mut self.frame[start..start + LINE_BYTES] {
+                    *byte = self.cpu.dma_out(&mut board);
+                }
+                self.cycle += LINE_BYTES as u32;
+                dma_line = Some(line);
+                continue;
+            }
+            if display && (INTERRUPT_START..DISPLAY_START).contains(&line) {
+                self.cycle += self.cpu.interrupt();
+            }
+
+            let idle = self.cpu.idle;
+            self.cycle += self.cpu.step(&mut board);
+            if !idle {
+                instructions += 1;
+            }
+        }
+        self.cycle -= FRAME_CYCLES;
+        if !board.devices.display_on {
+            self.frame = [0; DISPLAY_LINES * LINE_BYTES];
+        }
+        instructions
+    }
+
+    // The picture as CHIP-8 pixels. The interpreter shows each row of the display buffer on 4 scan lines,
+    // so one 64x32 row is taken from the middle of every 4 lines, where it's stable even if the DMA
+    // timing is a line off
+    pub fn screen(&self) -> [bool; WIDTH * HEIGHT] {
+        let mut screen = [false; WIDTH * HEIGHT];
+        for (row, pixels) in screen.chunks_mut(WIDTH).enumerate() {
+            let line = (row * DISPLAY_LINES / HEIGHT + 2) * LINE_BYTES;
+            for (x, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = self.frame[line + x / 8] & (0x80 >> (x % 8)) != 0;
+            }
+        }
+        screen
+    }
+
+    // Q drives the speaker
+    pub fn sound(&self) -> bool {
+        self.cpu.q
+    }
+
+    // CHIP-8 registers as the interpreter keeps them: the program counter in R5, I in RA, and V0-VF
+    // at the top of the page R6 points into
+    pub fn registers(&self) -> (u16, u16, usize) {
+        let v_base = (self.cpu.r[6] & 0xFF00 | 0x00F0) as usize % RAM_SIZE;
+        (self.cpu.r[5], self.cpu.r[0xA], v_base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just enough monitor to do what the real one does at power on: switch to R2 in the ROM, which ends the
+    // mirror at 0x0000, then jump to the interpreter with R0 as the program counter
+    const MONITOR: &[u8] = &[
+        0xF8, 0x80, 0xB2, 0xF8, 0x06, 0xA2, 0xD2, // R2 = 0x8006, SEP 2
+        0xF8, 0x00, 0xB0, 0xA0, 0xD0,             // R0 = 0x0000, SEP 0
+    ];
+
+    // A stand-in for the CHIP-8 interpreter. It points the display interrupt at a routine that shows
+    // 0x0200 onwards, latches key 5 with OUT 2, switches the display on with INP 1, then waits for
+    // EF3 and marks 0x0F00 with AA once the key is down
+    fn interpreter() -> Vec<u8> {
+        let mut code = vec![0; 0x51];
+        code[0x00..0x07].copy_from_slice(&[0xF8, 0x10, 0xA3, 0xF8, 0x00, 0xB3, 0xD3]); // R3 = 0x0010, SEP 3
+        code[0x10..0x2F].copy_from_slice(&[
+            0xF8, 0x00, 0xB1, 0xF8, 0x41, 0xA1, // R1 = 0x0041
+            0xF8, 0x0F, 0xB2, 0xB4,             // R2 = 0x0FF0, R4 = 0x0F00
+            0xF8, 0xF0, 0xA2, 0xF8, 0x00, 0xA4,
+            0xE2, 0xF8, 0x05, 0x52, 0x62,       // SEX 2, OUT 2 of 5
+            0x69,                               // INP 1
+            0x36, 0x2A, 0x30, 0x26,             // B3 2A, BR 26
+            0xF8, 0xAA, 0x54, 0x30, 0x2D,       // M(R4) = AA, then loop
+        ]);
+        // The interrupt routine keeps D and returns with SAV/RET, leaving R1 on its entry point. Like the
+        // real one it outlasts the two lines the interrupt is requested for, so it only runs once a frame
+        code[0x3F..0x51].copy_from_slice(&[
+            0x72, 0x70,                         // LDXA, RET
+            0x22, 0x78, 0x22, 0x52,             // Push T and D
+            0xF8, 0x02, 0xB0, 0xF8, 0x00, 0xA0, // R0 = 0x0200
+            0xC4, 0xC4, 0xC4, 0xC4,             // NOPs
+            0x30, 0x3F,
+        ]);
+        code
+    }
+
+    fn run(keys: &[bool; 16]) -> (Vip, [u8; RAM_SIZE]) {
+        let mut vip = Vip::new(&interpreter(), MONITOR).unwrap();
+        let mut memory = [0; RAM_SIZE];
+        vip.reset(&mut memory);
+        // Every CHIP-8 row is shown on 4 scan lines, the screen is read from the third
+        memory[0x200 + 2 * LINE_BYTES] = 0x80;
+        memory[0x200 + 6 * LINE_BYTES] = 0x01;
+        let mut written = [false; RAM_SIZE];
+        for _ in 0..3 {
+            vip.run_frame(&mut memory, &mut written, keys);
+        }
+        (vip, memory)
+    }
+
+    #[test]
+    fn boots_and_shows_the_display_buffer() {
+        let (vip, _) = run(&[false; 16]);
+        let screen = vip.screen();
+        let lit: Vec<usize> = (0..WIDTH * HEIGHT).filter(|&pixel| screen[pixel]).collect();
+        assert_eq!(lit, [0, WIDTH + 7]);
+    }
+
+    #[test]
+    fn keypad_latch() {
+        let mut keys = [false; 16];
+        keys[4] = true;
+        assert_eq!(run(&keys).1[0xF00], 0x00);
+        keys[5] = true;
+        assert_eq!(run(&keys).1[0xF00], 0xAA);
+    }
+}
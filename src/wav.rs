@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::tone::{AudioConfig, Tone};
+use crate::timer::Timer;
+
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_TICK: u32 = SAMPLE_RATE / 60; // One 60Hz timer tick worth of samples
+
+// Renders the beeper to a 16-bit mono WAV file, one timer tick at a time.
+// The output only depends on the sound timer state at each tick, not on the audio device.
+pub struct WavRecorder {
+    writer: BufWriter<File>,
+    tone: Tone,
+    samples: u32,
+}
+
+impl WavRecorder {
+    pub fn new(path: &str, config: AudioConfig) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        // Sizes are patched in once the recording is finished
+        Self::write_header(&mut writer, 0)?;
+        Ok(WavRecorder {
+            writer,
+            tone: Tone::new(config, SAMPLE_RATE as f32),
+            samples: 0,
+        })
+    }
+
+    // Render 1/60 s of audio with the beeper gated by the sound timer
+    pub fn tick(&mut self, st: u8) -> std::io::Result<()> {
+        for _ in 0..SAMPLES_PER_TICK {
+            let sample = (self.tone.next_sample(st != 0) * i16::MAX as f32) as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.samples += SAMPLES_PER_TICK;
+        Ok(())
+    }
+
+    // Patch the header with the final sizes and flush everything to disk
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        Self::write_header(&mut self.writer, self.samples * 2)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+
+    fn write_header(writer: &mut impl Write, data_len: u32) -> std::io::Result<()> {
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_len).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;              // fmt chunk size
+        writer.write_all(&1u16.to_le_bytes())?;               // PCM
+        writer.write_all(&1u16.to_le_bytes())?;               // Mono
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;  // Byte rate
+        writer.write_all(&2u16.to_le_bytes())?;               // Block align
+        writer.write_all(&16u16.to_le_bytes())?;              // Bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&data_len.to_le_bytes())
+    }
+}
+
+// A WavRecorder fed by the timer on each emulated 60Hz tick. The first write error stops the recording and
+// is kept for finish to report
+pub struct WavRecording {
+    recorder: Arc<Mutex<Result<Option<WavRecorder>, std::io::Error>>>,
+}
+
+impl WavRecording {
+    pub fn start(timer: &mut Timer, recorder: WavRecorder) -> Self {
+        let recorder = Arc::new(Mutex::new(Ok(Some(recorder))));
+        let hook_recorder = Arc::clone(&recorder);
+        timer.set_tick_hook(move |st| {
+            let mut recorder = hook_recorder.lock().unwrap();
+            if let Ok(Some(wav)) = recorder.as_mut() {
+                if let Err(err) = wav.tick(st) {
+                    *recorder = Err(err);
+                }
+            }
+        });
+        WavRecording { recorder }
+    }
+
+    // Stop recording and write out the file. Later ticks are ignored
+    pub fn finish(&self) -> std::io::Result<()> {
+        match std::mem::replace(&mut *self.recorder.lock().unwrap(), Ok(None)) {
+            Ok(Some(mut recorder)) => recorder.finish(),
+            Ok(None) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}